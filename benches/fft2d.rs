@@ -1,14 +1,15 @@
 use criterion::{criterion_group, criterion_main, Criterion};
 use ndarray::Array2;
 use ndfft_test::ndfft_with_ndrustfft::fft2d_with_ndrustfft;
-use ndfft_test::ndfft_with_transpose::fft2d_with_transpose;
+use ndfft_test::ndfft_with_transpose::Normalization;
+use ndfft_test::plan::Fft2dPlan;
 use ndfft_test::test_array::test_array2;
 use ndfft_test::test_array::test_vec;
 use ndrustfft::FftHandler;
 use rustfft::num_complex::Complex;
-use rustfft::{Fft, FftPlanner};
-use std::sync::Arc;
+use rustfft::FftDirection;
 const AXIS: usize = 0;
+const NORM: Normalization = Normalization::Backward;
 const FFT_SIZES: [usize; 4] = [128, 256, 512, 1024];
 
 pub fn bench_fft2d_with_ndrustfft(c: &mut Criterion) {
@@ -30,12 +31,21 @@ pub fn bench_fft2d_with_transpose(c: &mut Criterion) {
     for n in FFT_SIZES.into_iter() {
         let name = format!("Size: {}", n);
         let mut v = test_vec(n * n);
-        let mut scratch = vec![Complex::default(); n * n];
-        let mut planner = FftPlanner::<f64>::new();
-        let fft: Arc<dyn Fft<f64>> = planner.plan_fft_forward(n);
-        group.bench_function(&name, |b| {
-            b.iter(|| fft2d_with_transpose(&mut v, &mut scratch, n, n, &fft, AXIS))
-        });
+        let mut plan = Fft2dPlan::new((n, n), AXIS, FftDirection::Forward).normalization(NORM);
+        group.bench_function(&name, |b| b.iter(|| plan.process(&mut v)));
+    }
+    group.finish();
+}
+
+pub fn bench_fft2d_with_transpose_par(c: &mut Criterion) {
+    let mut group = c.benchmark_group("fft2d (transpose, parallel) ");
+    for n in FFT_SIZES.into_iter() {
+        let name = format!("Size: {}", n);
+        let mut v = test_vec(n * n);
+        let mut plan = Fft2dPlan::new((n, n), AXIS, FftDirection::Forward)
+            .normalization(NORM)
+            .parallel(true);
+        group.bench_function(&name, |b| b.iter(|| plan.process(&mut v)));
     }
     group.finish();
 }
@@ -43,6 +53,7 @@ pub fn bench_fft2d_with_transpose(c: &mut Criterion) {
 criterion_group!(
     benches,
     bench_fft2d_with_ndrustfft,
-    bench_fft2d_with_transpose
+    bench_fft2d_with_transpose,
+    bench_fft2d_with_transpose_par
 );
 criterion_main!(benches);
@@ -0,0 +1,440 @@
+//! Batched 2D ffts of a stack of k frames of size m x n
+//!
+//! The stack is stored contiguously as a flattened (k, m, n) array. All frames
+//! are transformed with shared plans and a shared scratch. Along axis 0 of the
+//! frames, two strategies are used:
+//!
+//! - Small frames: a single transpose of the whole stack, viewed as a
+//!   (k * m) x n matrix, makes the lanes of all frames contiguous at once
+//! - Large frames: each frame is transposed separately, which keeps the
+//!   transposes in cache
+//!
+//! In both cases all lanes of all frames are transformed by a single call
+//! to the fft.
+//!
+//! # Example
+//! ```
+//! use ndfft_test::batch::Fft2dBatchPlan;
+//! use rustfft::num_complex::Complex;
+//! use rustfft::FftDirection;
+//!
+//! let (k, m, n) = (10, 6, 4);
+//! let mut data = vec![Complex::new(1., 0.); k * m * n];
+//! let mut plan = Fft2dBatchPlan::full((k, m, n), FftDirection::Forward);
+//! plan.process(&mut data);
+//! ```
+use crate::ndfft_with_transpose::{
+    assert_direction, assert_length, fftnd_axis_scaled, process_lanes_par, transpose_scaled,
+    Normalization,
+};
+use crate::transpose::oop_transpose;
+use rayon::prelude::*;
+use rustfft::{num_complex::Complex, Fft, FftDirection, FftPlanner};
+use std::sync::Arc;
+
+/// Frames up to this size are transformed with a single transpose of the stack
+const SIZE_PERMUTE: usize = 64 * 64;
+
+/// Fft along `axis` of every frame of a flattened (k, m, n) stack
+///
+/// # Arguments
+///
+/// * `v` - Flattened stack with k * m * n elements, input and output
+/// * `scratch` - Work-space, at least k * m * n + `fft.get_inplace_scratch_len()`
+///   elements for `axis` = 0 and `fft.get_inplace_scratch_len()` elements
+///   for `axis` = 1
+/// * `k` - Number of frames
+/// * `m` - Number of rows of each frame
+/// * `n` - Number of cols of each frame
+/// * `fft` - Forward fft plan of length m (`axis` = 0) or n (`axis` = 1)
+/// * `axis` - Axis of the frames along which the fft is performed
+/// * `norm` - Normalization of the transform
+///
+/// # Panics
+///
+/// Panics if the length of `fft` does not match the lane length along `axis`,
+/// if `fft` is not a forward plan, or if `v` or `scratch` are too small.
+#[allow(clippy::too_many_arguments)]
+pub fn fft2d_batch(
+    v: &mut [Complex<f64>],
+    scratch: &mut [Complex<f64>],
+    k: usize,
+    m: usize,
+    n: usize,
+    fft: &Arc<dyn Fft<f64>>,
+    axis: usize,
+    norm: Normalization,
+) {
+    assert_direction(fft, FftDirection::Forward);
+    batch_axis(v, scratch, (k, m, n), fft, axis, norm, false);
+}
+
+/// Inverse fft along `axis` of every frame of a flattened (k, m, n) stack
+///
+/// Counterpart of [`fft2d_batch`].
+///
+/// # Arguments
+///
+/// * `v` - Flattened stack with k * m * n elements, input and output
+/// * `scratch` - Work-space, at least k * m * n + `ifft.get_inplace_scratch_len()`
+///   elements for `axis` = 0 and `ifft.get_inplace_scratch_len()` elements
+///   for `axis` = 1
+/// * `k` - Number of frames
+/// * `m` - Number of rows of each frame
+/// * `n` - Number of cols of each frame
+/// * `ifft` - Inverse fft plan of length m (`axis` = 0) or n (`axis` = 1)
+/// * `axis` - Axis of the frames along which the fft is performed
+/// * `norm` - Normalization of the transform
+///
+/// # Panics
+///
+/// Panics if the length of `ifft` does not match the lane length along `axis`,
+/// if `ifft` is not an inverse plan, or if `v` or `scratch` are too small.
+#[allow(clippy::too_many_arguments)]
+pub fn ifft2d_batch(
+    v: &mut [Complex<f64>],
+    scratch: &mut [Complex<f64>],
+    k: usize,
+    m: usize,
+    n: usize,
+    ifft: &Arc<dyn Fft<f64>>,
+    axis: usize,
+    norm: Normalization,
+) {
+    assert_direction(ifft, FftDirection::Inverse);
+    batch_axis(v, scratch, (k, m, n), ifft, axis, norm, false);
+}
+
+/// Multithreaded fft along `axis` of every frame of a flattened (k, m, n) stack
+///
+/// Same as [`fft2d_batch`], but the frames are distributed over the current
+/// rayon thread pool. Along axis 0 each frame is transposed into its own part
+/// of `scratch`, the fft scratch is allocated per task.
+///
+/// # Arguments
+///
+/// * `v` - Flattened stack with k * m * n elements, input and output
+/// * `scratch` - Work-space, at least k * m * n elements for `axis` = 0,
+///   unused for `axis` = 1
+/// * `k` - Number of frames
+/// * `m` - Number of rows of each frame
+/// * `n` - Number of cols of each frame
+/// * `fft` - Forward fft plan of length m (`axis` = 0) or n (`axis` = 1)
+/// * `axis` - Axis of the frames along which the fft is performed
+/// * `norm` - Normalization of the transform
+///
+/// # Panics
+///
+/// Panics if the length of `fft` does not match the lane length along `axis`,
+/// if `fft` is not a forward plan, or if `v` or `scratch` are too small.
+#[allow(clippy::too_many_arguments)]
+pub fn fft2d_batch_par(
+    v: &mut [Complex<f64>],
+    scratch: &mut [Complex<f64>],
+    k: usize,
+    m: usize,
+    n: usize,
+    fft: &Arc<dyn Fft<f64>>,
+    axis: usize,
+    norm: Normalization,
+) {
+    assert_direction(fft, FftDirection::Forward);
+    batch_axis(v, scratch, (k, m, n), fft, axis, norm, true);
+}
+
+/// Multithreaded inverse fft along `axis` of every frame of a flattened
+/// (k, m, n) stack
+///
+/// Counterpart of [`fft2d_batch_par`].
+///
+/// # Arguments
+///
+/// * `v` - Flattened stack with k * m * n elements, input and output
+/// * `scratch` - Work-space, at least k * m * n elements for `axis` = 0,
+///   unused for `axis` = 1
+/// * `k` - Number of frames
+/// * `m` - Number of rows of each frame
+/// * `n` - Number of cols of each frame
+/// * `ifft` - Inverse fft plan of length m (`axis` = 0) or n (`axis` = 1)
+/// * `axis` - Axis of the frames along which the fft is performed
+/// * `norm` - Normalization of the transform
+///
+/// # Panics
+///
+/// Panics if the length of `ifft` does not match the lane length along `axis`,
+/// if `ifft` is not an inverse plan, or if `v` or `scratch` are too small.
+#[allow(clippy::too_many_arguments)]
+pub fn ifft2d_batch_par(
+    v: &mut [Complex<f64>],
+    scratch: &mut [Complex<f64>],
+    k: usize,
+    m: usize,
+    n: usize,
+    ifft: &Arc<dyn Fft<f64>>,
+    axis: usize,
+    norm: Normalization,
+) {
+    assert_direction(ifft, FftDirection::Inverse);
+    batch_axis(v, scratch, (k, m, n), ifft, axis, norm, true);
+}
+
+/// Fft along a single axis of all frames in the direction of `fft`
+fn batch_axis(
+    v: &mut [Complex<f64>],
+    scratch: &mut [Complex<f64>],
+    shape: (usize, usize, usize),
+    fft: &Arc<dyn Fft<f64>>,
+    axis: usize,
+    norm: Normalization,
+    parallel: bool,
+) {
+    let (k, m, n) = shape;
+    let size = k * m * n;
+    assert!(v.len() == size, "{} != {}", v.len(), size);
+    assert!(axis < 2, "axis must be 0 or 1, got {}", axis);
+    let lane_len = if axis == 0 { m } else { n };
+    assert_length(fft, lane_len, axis);
+    let scale = norm.factor(lane_len, fft.fft_direction());
+    if size == 0 {
+        return;
+    }
+    if axis == 1 {
+        if parallel {
+            process_lanes_par(v, fft, scale);
+        } else {
+            fftnd_axis_scaled(v, scratch, &[k, m, n], fft, 2, scale);
+        }
+        return;
+    }
+    if !parallel && m * n > SIZE_PERMUTE {
+        fftnd_axis_scaled(v, scratch, &[k, m, n], fft, 1, scale);
+        return;
+    }
+    let scratch_len = fft.get_inplace_scratch_len();
+    let extra_len = if parallel { 0 } else { scratch_len };
+    assert!(scratch.len() >= size + extra_len);
+    let (buffer, extra) = scratch.split_at_mut(size);
+    if parallel {
+        let frame = m * n;
+        v.par_chunks_mut(frame)
+            .zip(buffer.par_chunks_mut(frame))
+            .for_each_init(
+                || vec![Complex::default(); scratch_len],
+                |fft_scratch, (x, buffer)| {
+                    oop_transpose(x, buffer, m, n);
+                    fft.process_with_scratch(buffer, fft_scratch);
+                    transpose_scaled(buffer, x, n, m, scale);
+                },
+            );
+    } else {
+        // The (k * m) x n stack transposed is n x (k * m), whose rows are
+        // made up of k contiguous lanes of length m
+        oop_transpose(v, buffer, k * m, n);
+        fft.process_with_scratch(buffer, &mut extra[..extra_len]);
+        transpose_scaled(buffer, v, n, k * m, scale);
+    }
+}
+
+/// Planned batched 2D fft of a flattened (k, m, n) stack along one or both
+/// axes of the frames
+pub struct Fft2dBatchPlan {
+    k: usize,
+    m: usize,
+    n: usize,
+    /// Axis of the transform, `None` for both axes
+    axis: Option<usize>,
+    direction: FftDirection,
+    norm: Normalization,
+    /// Distribute the frames over the rayon thread pool
+    parallel: bool,
+    fft_m: Arc<dyn Fft<f64>>,
+    fft_n: Arc<dyn Fft<f64>>,
+    scratch: Vec<Complex<f64>>,
+}
+
+impl Fft2dBatchPlan {
+    /// Plan an fft along `axis` of each frame of a stack of size `shape`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `axis` is not 0 or 1.
+    pub fn new(shape: (usize, usize, usize), axis: usize, direction: FftDirection) -> Self {
+        assert!(axis < 2, "axis must be 0 or 1, got {}", axis);
+        Self::with_axis(shape, Some(axis), direction)
+    }
+
+    /// Plan an fft along both axes of each frame of a stack of size `shape`
+    pub fn full(shape: (usize, usize, usize), direction: FftDirection) -> Self {
+        Self::with_axis(shape, None, direction)
+    }
+
+    fn with_axis(
+        shape: (usize, usize, usize),
+        axis: Option<usize>,
+        direction: FftDirection,
+    ) -> Self {
+        let (k, m, n) = shape;
+        let mut planner = FftPlanner::<f64>::new();
+        let fft_m = planner.plan_fft(m, direction);
+        let fft_n = planner.plan_fft(n, direction);
+        let mut plan = Self {
+            k,
+            m,
+            n,
+            axis,
+            direction,
+            norm: Normalization::default(),
+            parallel: false,
+            fft_m,
+            fft_n,
+            scratch: vec![],
+        };
+        plan.scratch = vec![Complex::default(); plan.required_scratch_len()];
+        plan
+    }
+
+    /// Scratch required for the current axis
+    fn required_scratch_len(&self) -> usize {
+        let size = self.k * self.m * self.n;
+        let (m_inplace, n_inplace) = if self.parallel {
+            (0, 0)
+        } else {
+            (
+                self.fft_m.get_inplace_scratch_len(),
+                self.fft_n.get_inplace_scratch_len(),
+            )
+        };
+        match self.axis {
+            Some(0) => size + m_inplace,
+            Some(_) => n_inplace,
+            None => (size + m_inplace).max(n_inplace),
+        }
+    }
+
+    /// Set the normalization, default is [`Normalization::Backward`]
+    #[must_use]
+    pub fn normalization(mut self, norm: Normalization) -> Self {
+        self.norm = norm;
+        self
+    }
+
+    /// Distribute the frames over the rayon thread pool, see
+    /// [`fft2d_batch_par`]
+    #[must_use]
+    pub fn parallel(mut self, parallel: bool) -> Self {
+        self.parallel = parallel;
+        self.scratch = vec![Complex::default(); self.required_scratch_len()];
+        self
+    }
+
+    /// Shape (k, m, n) of the stack
+    pub fn shape(&self) -> (usize, usize, usize) {
+        (self.k, self.m, self.n)
+    }
+
+    /// Number of elements of the owned scratch buffer
+    pub fn scratch_len(&self) -> usize {
+        self.scratch.len()
+    }
+
+    /// Transform all frames of `data` in-place
+    ///
+    /// # Panics
+    ///
+    /// Panics if `data` does not have k * m * n elements.
+    pub fn process(&mut self, data: &mut [Complex<f64>]) {
+        let axes: &[(usize, &Arc<dyn Fft<f64>>)] = match self.axis {
+            Some(0) => &[(0, &self.fft_m)],
+            Some(_) => &[(1, &self.fft_n)],
+            None => &[(1, &self.fft_n), (0, &self.fft_m)],
+        };
+        let shape = (self.k, self.m, self.n);
+        for &(axis, fft) in axes {
+            batch_axis(
+                data,
+                &mut self.scratch,
+                shape,
+                fft,
+                axis,
+                self.norm,
+                self.parallel,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::plan::Fft2dPlan;
+    use crate::test_array::{approx_eq, test_vec};
+
+    /// Transform each frame separately with [`Fft2dPlan`]
+    fn per_frame(
+        v: &mut [Complex<f64>],
+        m: usize,
+        n: usize,
+        axis: Option<usize>,
+        norm: Normalization,
+    ) {
+        let mut plan = match axis {
+            Some(axis) => Fft2dPlan::new((m, n), axis, FftDirection::Forward),
+            None => Fft2dPlan::full((m, n), FftDirection::Forward),
+        }
+        .normalization(norm);
+        for frame in v.chunks_exact_mut(m * n) {
+            plan.process(frame);
+        }
+    }
+
+    #[test]
+    fn test_batch() {
+        let norm = Normalization::Ortho;
+        // Small frames use the single transpose, large frames per-frame transposes,
+        // (1, 509, 3) needs more fft scratch than the stack
+        for (k, m, n) in [(7, 12, 5), (3, 100, 70), (1, 9, 1), (1, 509, 3)] {
+            for axis in [Some(0), Some(1), None] {
+                let mut expected = test_vec(k * m * n);
+                per_frame(&mut expected, m, n, axis, norm);
+                for parallel in [false, true] {
+                    let (plan, iplan) = match axis {
+                        Some(axis) => (
+                            Fft2dBatchPlan::new((k, m, n), axis, FftDirection::Forward),
+                            Fft2dBatchPlan::new((k, m, n), axis, FftDirection::Inverse),
+                        ),
+                        None => (
+                            Fft2dBatchPlan::full((k, m, n), FftDirection::Forward),
+                            Fft2dBatchPlan::full((k, m, n), FftDirection::Inverse),
+                        ),
+                    };
+                    let mut plan = plan.normalization(norm).parallel(parallel);
+                    let mut iplan = iplan.normalization(norm).parallel(parallel);
+                    let mut v = test_vec(k * m * n);
+                    plan.process(&mut v);
+                    assert!(approx_eq(&v, &expected));
+                    iplan.process(&mut v);
+                    assert!(approx_eq(&v, &test_vec(k * m * n)));
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Size mismatch")]
+    fn test_batch_wrong_plan_length() {
+        let (k, m, n) = (4, 8, 6);
+        let fft = FftPlanner::<f64>::new().plan_fft_forward(n);
+        let mut v = test_vec(k * m * n);
+        let mut scratch = vec![Complex::default(); k * m * n];
+        fft2d_batch(
+            &mut v,
+            &mut scratch,
+            k,
+            m,
+            n,
+            &fft,
+            0,
+            Normalization::Backward,
+        );
+    }
+}
@@ -0,0 +1,394 @@
+//! Fft based 2D convolution and cross-correlation of flattened real arrays
+//!
+//! Follows the conventions of scipy's `convolve2d` and `correlate2d`:
+//!
+//! | Mode    | Output shape                      |
+//! | :---    | :---                              |
+//! | `Full`  | (ma + mk - 1) x (na + nk - 1)     |
+//! | `Same`  | ma x na, centered w.r.t. `Full`   |
+//! | `Valid` | (ma - mk + 1) x (na - nk + 1)     |
+//!
+//! where ma x na is the shape of the image and mk x nk the shape of the kernel.
+//! With [`Boundary::Zero`] the image is zero-padded (linear convolution), with
+//! [`Boundary::Circular`] it is periodic (scipy's `boundary='wrap'`).
+//!
+//! The transforms along both axes are done with [`Fft2dPlan`]. The spectra are
+//! kept in transposed layout, which saves the transposes back.
+//!
+//! # Example
+//! ```
+//! use ndfft_test::convolve::{convolve2d, Boundary, Mode};
+//!
+//! let image = vec![1.; 5 * 4];
+//! let kernel = vec![1.; 3 * 3];
+//! let out = convolve2d(&image, (5, 4), &kernel, (3, 3), Mode::Valid, Boundary::Zero);
+//! assert!(out.len() == 3 * 2);
+//! ```
+use crate::ndfft_with_transpose::Layout;
+use crate::plan::Fft2dPlan;
+use rustfft::{num_complex::Complex, FftDirection};
+
+/// Output size of a convolution
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Full discrete linear convolution
+    Full,
+    /// Same size as the image, centered w.r.t. the full output
+    Same,
+    /// Only points which do not depend on the boundary
+    Valid,
+}
+
+/// Boundary condition of the image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Boundary {
+    /// Periodic image
+    Circular,
+    /// Zero-padded image
+    Zero,
+}
+
+/// Shape of the output of a convolution or correlation
+///
+/// # Panics
+///
+/// Panics for [`Mode::Valid`] if the kernel is larger than the image.
+pub fn output_shape(
+    image_shape: (usize, usize),
+    kernel_shape: (usize, usize),
+    mode: Mode,
+) -> (usize, usize) {
+    let ((ma, na), (mk, nk)) = (image_shape, kernel_shape);
+    match mode {
+        Mode::Full => (ma + mk - 1, na + nk - 1),
+        Mode::Same => (ma, na),
+        Mode::Valid => {
+            assert!(
+                mk <= ma && nk <= na,
+                "Kernel {:?} larger than image {:?} in valid mode",
+                kernel_shape,
+                image_shape
+            );
+            (ma - mk + 1, na - nk + 1)
+        }
+    }
+}
+
+/// Convolution of a flattened ma x na `image` with a flattened mk x nk `kernel`
+///
+/// # Arguments
+///
+/// * `image` - Flattened 2D array with ma * na elements
+/// * `image_shape` - Shape (ma, na) of the image
+/// * `kernel` - Flattened 2D array with mk * nk elements
+/// * `kernel_shape` - Shape (mk, nk) of the kernel
+/// * `mode` - Output size, see [`output_shape`]
+/// * `boundary` - Boundary condition of the image
+///
+/// # Panics
+///
+/// Panics if the arrays do not match their shapes, for [`Mode::Valid`] if
+/// the kernel is larger than the image and for [`Boundary::Circular`] if the
+/// kernel is larger than the image.
+pub fn convolve2d(
+    image: &[f64],
+    image_shape: (usize, usize),
+    kernel: &[f64],
+    kernel_shape: (usize, usize),
+    mode: Mode,
+    boundary: Boundary,
+) -> Vec<f64> {
+    let mut convolver = Convolver2d::new(image_shape, kernel, kernel_shape, mode, boundary);
+    let mut out = vec![0.; convolver.len()];
+    convolver.process(image, &mut out);
+    out
+}
+
+/// Cross-correlation of a flattened ma x na `image` with a flattened
+/// mk x nk `kernel`
+///
+/// Equal to the convolution with the kernel reversed along both axes.
+/// See [`convolve2d`] for the arguments.
+///
+/// # Panics
+///
+/// Same as [`convolve2d`].
+pub fn correlate2d(
+    image: &[f64],
+    image_shape: (usize, usize),
+    kernel: &[f64],
+    kernel_shape: (usize, usize),
+    mode: Mode,
+    boundary: Boundary,
+) -> Vec<f64> {
+    let mut convolver = Convolver2d::correlation(image_shape, kernel, kernel_shape, mode, boundary);
+    let mut out = vec![0.; convolver.len()];
+    convolver.process(image, &mut out);
+    out
+}
+
+/// Reusable convolution of images of a fixed shape with a fixed kernel
+///
+/// Owns the fft plans, the spectrum of the kernel and the work buffer,
+/// so that repeated convolutions only transform the image.
+pub struct Convolver2d {
+    image_shape: (usize, usize),
+    /// Shape of the transforms, the full shape for [`Boundary::Zero`] and
+    /// the image shape for [`Boundary::Circular`]
+    fft_shape: (usize, usize),
+    out_shape: (usize, usize),
+    /// Offset of the output in the full convolution
+    offset: (usize, usize),
+    /// Spectrum of the kernel in transposed layout
+    kernel_hat: Vec<Complex<f64>>,
+    forward: Fft2dPlan,
+    inverse: Fft2dPlan,
+    buffer: Vec<Complex<f64>>,
+}
+
+impl Convolver2d {
+    /// Plan the convolution of ma x na images with a mk x nk `kernel`
+    ///
+    /// # Panics
+    ///
+    /// Same as [`convolve2d`].
+    pub fn new(
+        image_shape: (usize, usize),
+        kernel: &[f64],
+        kernel_shape: (usize, usize),
+        mode: Mode,
+        boundary: Boundary,
+    ) -> Self {
+        let (mk, nk) = kernel_shape;
+        assert!(kernel.len() == mk * nk, "{} != {}", kernel.len(), mk * nk);
+        let ((ma, na), out_shape) = (image_shape, output_shape(image_shape, kernel_shape, mode));
+        let fft_shape = match boundary {
+            Boundary::Zero => (ma + mk - 1, na + nk - 1),
+            Boundary::Circular => {
+                assert!(
+                    mk <= ma && nk <= na,
+                    "Kernel {:?} larger than image {:?} with circular boundary",
+                    kernel_shape,
+                    image_shape
+                );
+                image_shape
+            }
+        };
+        let offset = match mode {
+            Mode::Full => (0, 0),
+            Mode::Same => ((mk - 1) / 2, (nk - 1) / 2),
+            Mode::Valid => (mk - 1, nk - 1),
+        };
+        let (pm, pn) = fft_shape;
+        let mut forward =
+            Fft2dPlan::full(fft_shape, FftDirection::Forward).layout(Layout::Transposed);
+        let inverse = Fft2dPlan::full(fft_shape, FftDirection::Inverse).layout(Layout::Transposed);
+        let mut kernel_hat = vec![Complex::default(); pm * pn];
+        embed(kernel, kernel_shape, &mut kernel_hat, pn);
+        forward.process(&mut kernel_hat);
+        Self {
+            image_shape,
+            fft_shape,
+            out_shape,
+            offset,
+            kernel_hat,
+            forward,
+            inverse,
+            buffer: vec![Complex::default(); pm * pn],
+        }
+    }
+
+    /// Plan the cross-correlation of ma x na images with a mk x nk `kernel`
+    ///
+    /// # Panics
+    ///
+    /// Same as [`convolve2d`].
+    pub fn correlation(
+        image_shape: (usize, usize),
+        kernel: &[f64],
+        kernel_shape: (usize, usize),
+        mode: Mode,
+        boundary: Boundary,
+    ) -> Self {
+        let reversed: Vec<f64> = kernel.iter().rev().copied().collect();
+        Self::new(image_shape, &reversed, kernel_shape, mode, boundary)
+    }
+
+    /// Shape of the output
+    pub fn output_shape(&self) -> (usize, usize) {
+        self.out_shape
+    }
+
+    /// Number of elements of the output
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        self.out_shape.0 * self.out_shape.1
+    }
+
+    /// Convolve `image` with the kernel, the result is written to `out`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `image` or `out` do not match the image and output shapes.
+    pub fn process(&mut self, image: &[f64], out: &mut [f64]) {
+        let (ma, na) = self.image_shape;
+        let (pm, pn) = self.fft_shape;
+        let (om, on) = self.out_shape;
+        let (oi, oj) = self.offset;
+        assert!(image.len() == ma * na, "{} != {}", image.len(), ma * na);
+        assert!(out.len() == om * on, "{} != {}", out.len(), om * on);
+        embed(image, self.image_shape, &mut self.buffer, pn);
+        self.forward.process(&mut self.buffer);
+        for (x, k) in self.buffer.iter_mut().zip(self.kernel_hat.iter()) {
+            *x *= k;
+        }
+        self.inverse.process(&mut self.buffer);
+        for (i, row) in out.chunks_exact_mut(on).enumerate() {
+            let src = &self.buffer[((i + oi) % pm) * pn..][..pn];
+            for (j, y) in row.iter_mut().enumerate() {
+                *y = src[(j + oj) % pn].re;
+            }
+        }
+    }
+}
+
+/// Copy a flattened real array of `shape` into the top left corner of the
+/// zeroed, flattened complex array `dst` with `cols` columns
+fn embed(src: &[f64], shape: (usize, usize), dst: &mut [Complex<f64>], cols: usize) {
+    let (m, n) = shape;
+    dst.fill(Complex::default());
+    if n == 0 {
+        return;
+    }
+    for (src_row, dst_row) in src.chunks_exact(n).zip(dst.chunks_exact_mut(cols)).take(m) {
+        for (x, y) in src_row.iter().zip(dst_row.iter_mut()) {
+            y.re = *x;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const MODES: [Mode; 3] = [Mode::Full, Mode::Same, Mode::Valid];
+    const BOUNDARIES: [Boundary; 2] = [Boundary::Zero, Boundary::Circular];
+
+    fn test_data(m: usize, n: usize, seed: usize) -> Vec<f64> {
+        (0..m * n)
+            .map(|x| ((x * 7 + seed) % 11) as f64 - 5.)
+            .collect()
+    }
+
+    fn assert_approx_eq(result: &[f64], expected: &[f64]) {
+        let scale = expected.iter().fold(1., |a: f64, b| a.max(b.abs()));
+        assert!(result.len() == expected.len());
+        for (a, b) in result.iter().zip(expected.iter()) {
+            assert!((a - b).abs() < 1e-10 * scale, "{} != {}", a, b);
+        }
+    }
+
+    /// Direct convolution, following the definitions of scipy's convolve2d
+    fn convolve_direct(
+        a: &[f64],
+        (ma, na): (usize, usize),
+        k: &[f64],
+        (mk, nk): (usize, usize),
+        mode: Mode,
+        boundary: Boundary,
+    ) -> Vec<f64> {
+        let (om, on) = output_shape((ma, na), (mk, nk), mode);
+        let (oi, oj) = match mode {
+            Mode::Full => (0, 0),
+            Mode::Same => ((mk - 1) / 2, (nk - 1) / 2),
+            Mode::Valid => (mk - 1, nk - 1),
+        };
+        let mut out = vec![0.; om * on];
+        for i in 0..om {
+            for j in 0..on {
+                let mut sum = 0.;
+                for p in 0..mk {
+                    for q in 0..nk {
+                        let r = (i + oi) as isize - p as isize;
+                        let c = (j + oj) as isize - q as isize;
+                        let (r, c) = match boundary {
+                            Boundary::Circular => {
+                                (r.rem_euclid(ma as isize), c.rem_euclid(na as isize))
+                            }
+                            Boundary::Zero => {
+                                if r < 0 || c < 0 || r >= ma as isize || c >= na as isize {
+                                    continue;
+                                }
+                                (r, c)
+                            }
+                        };
+                        sum += k[p * nk + q] * a[r as usize * na + c as usize];
+                    }
+                }
+                out[i * on + j] = sum;
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn test_convolve2d() {
+        for (image_shape, kernel_shape) in [((12, 9), (3, 4)), ((7, 16), (5, 1)), ((8, 8), (8, 8))]
+        {
+            let image = test_data(image_shape.0, image_shape.1, 0);
+            let kernel = test_data(kernel_shape.0, kernel_shape.1, 3);
+            for mode in MODES {
+                for boundary in BOUNDARIES {
+                    let expected =
+                        convolve_direct(&image, image_shape, &kernel, kernel_shape, mode, boundary);
+                    let out =
+                        convolve2d(&image, image_shape, &kernel, kernel_shape, mode, boundary);
+                    assert_approx_eq(&out, &expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_correlate2d() {
+        let (image_shape, kernel_shape) = ((10, 13), (4, 3));
+        let image = test_data(image_shape.0, image_shape.1, 0);
+        let kernel = test_data(kernel_shape.0, kernel_shape.1, 3);
+        let reversed: Vec<f64> = kernel.iter().rev().copied().collect();
+        for mode in MODES {
+            for boundary in BOUNDARIES {
+                let expected =
+                    convolve_direct(&image, image_shape, &reversed, kernel_shape, mode, boundary);
+                let out = correlate2d(&image, image_shape, &kernel, kernel_shape, mode, boundary);
+                assert_approx_eq(&out, &expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_convolver_reuse() {
+        let (image_shape, kernel_shape) = ((16, 12), (3, 3));
+        let kernel = test_data(kernel_shape.0, kernel_shape.1, 1);
+        let mut convolver = Convolver2d::new(
+            image_shape,
+            &kernel,
+            kernel_shape,
+            Mode::Same,
+            Boundary::Zero,
+        );
+        let mut out = vec![0.; convolver.len()];
+        for seed in 0..3 {
+            let image = test_data(image_shape.0, image_shape.1, seed);
+            convolver.process(&image, &mut out);
+            let expected = convolve_direct(
+                &image,
+                image_shape,
+                &kernel,
+                kernel_shape,
+                Mode::Same,
+                Boundary::Zero,
+            );
+            assert_approx_eq(&out, &expected);
+        }
+    }
+}
@@ -0,0 +1,297 @@
+//! Chirp-Z transform along one axis of a flattened 2D array
+//!
+//! The chirp-Z transform evaluates the z-transform of each lane of length n
+//! at the m points z_k = a w^(-k) on a spiral arc of the complex plane:
+//!
+//! X_k = sum_j x_j a^(-j) w^(j k),    k = 0, ..., m - 1
+//!
+//! With |a| = |w| = 1 the points lie on the unit circle, which yields the
+//! zoom fft, i.e. a dft of high resolution over a narrow band, see
+//! [`Czt2d::zoom`].
+//!
+//! The transform is computed with Bluestein's algorithm as a circular
+//! convolution of length l >= n + m - 1. The lanes along `axis` are made
+//! contiguous by the strided transpose of [`crate::transpose`], which is
+//! fused with the chirp premultiplication and writes into the zero-padded
+//! lanes, all lanes are convolved by a batched fft with the planned spectrum
+//! of the chirp kernel, and the result is transposed back, fused with the
+//! chirp postmultiplication.
+//!
+//! # Example
+//! ```
+//! use ndfft_test::czt::Czt2d;
+//! use rustfft::num_complex::Complex;
+//!
+//! // Spectrum of 256 points between 0.1 and 0.15 of the sampling rate
+//! let (rows, cols) = (8, 64);
+//! let v = vec![Complex::new(1., 0.); rows * cols];
+//! let mut czt = Czt2d::zoom((rows, cols), 1, 256, (0.1, 0.15), 1.);
+//! let mut vhat = vec![Complex::new(0., 0.); czt.output_len()];
+//! czt.process(&v, &mut vhat);
+//! ```
+use crate::transpose::oop_transpose_strided_map;
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+/// Reusable chirp-Z transform along one axis of a flattened rows x cols array
+pub struct Czt2d {
+    rows: usize,
+    cols: usize,
+    axis: usize,
+    /// Number of output points
+    m: usize,
+    /// Length of the circular convolution
+    l: usize,
+    fft: Arc<dyn Fft<f64>>,
+    ifft: Arc<dyn Fft<f64>>,
+    /// Chirp a^(-j) w^(j^2 / 2) of the input
+    pre: Vec<Complex<f64>>,
+    /// Chirp w^(k^2 / 2) of the output
+    post: Vec<Complex<f64>>,
+    /// Spectrum of the kernel w^(-l^2 / 2), scaled by 1 / l
+    kernel: Vec<Complex<f64>>,
+    /// Padded lanes, lanes x l
+    buffer: Vec<Complex<f64>>,
+    scratch: Vec<Complex<f64>>,
+}
+
+impl Czt2d {
+    /// Plan a chirp-Z transform of `m` points along `axis` of an array of
+    /// size `shape`, on the arc z_k = a w^(-k)
+    ///
+    /// # Panics
+    ///
+    /// Panics if `axis` is not 0 or 1, if `m` is zero, or if `w` or `a` are zero.
+    pub fn new(
+        shape: (usize, usize),
+        axis: usize,
+        m: usize,
+        w: Complex<f64>,
+        a: Complex<f64>,
+    ) -> Self {
+        assert!(axis < 2, "axis must be 0 or 1, got {}", axis);
+        assert!(m > 0, "number of points must be positive");
+        assert!(w.norm() > 0. && a.norm() > 0., "w and a must be non-zero");
+        let (rows, cols) = shape;
+        let n = if axis == 0 { rows } else { cols };
+        let lanes = if axis == 0 { cols } else { rows };
+        let l = (n + m).max(2) - 1;
+        let mut planner = FftPlanner::new();
+        let fft = planner.plan_fft_forward(l);
+        let ifft = planner.plan_fft_inverse(l);
+        // w^x = exp(x ln w), the jk = (j^2 + k^2 - (k - j)^2) / 2 identity
+        // holds for any branch of ln w
+        let ln_w = w.ln();
+        let chirp = |j: usize| (ln_w * (j * j) as f64 * 0.5).exp();
+        let a_inv = a.inv();
+        let pre = (0..n).map(|j| a_inv.powu(j as u32) * chirp(j)).collect();
+        let post = (0..m).map(chirp).collect();
+        let mut kernel = vec![Complex::default(); l];
+        for (k, x) in kernel.iter_mut().enumerate().take(m) {
+            *x = chirp(k).inv();
+        }
+        for j in 1..n {
+            kernel[l - j] = chirp(j).inv();
+        }
+        let mut scratch = vec![Complex::default(); fft.get_inplace_scratch_len()];
+        fft.process_with_scratch(&mut kernel, &mut scratch);
+        let scale = 1. / l as f64;
+        kernel.iter_mut().for_each(|x| *x *= scale);
+        let scratch_len = fft
+            .get_inplace_scratch_len()
+            .max(ifft.get_inplace_scratch_len());
+        Self {
+            rows,
+            cols,
+            axis,
+            m,
+            l,
+            fft,
+            ifft,
+            pre,
+            post,
+            kernel,
+            buffer: vec![Complex::default(); lanes * l],
+            scratch: vec![Complex::default(); scratch_len],
+        }
+    }
+
+    /// Plan a zoom fft of `m` points along `axis`, i.e. a dft at the
+    /// frequencies of [`zoom_frequencies`]
+    ///
+    /// # Arguments
+    ///
+    /// * `shape` - Shape (rows, cols) of the input
+    /// * `axis` - Axis along which the transform is performed
+    /// * `m` - Number of output points
+    /// * `band` - Frequency band (f0, f1), f1 is excluded
+    /// * `fs` - Sampling rate
+    pub fn zoom(shape: (usize, usize), axis: usize, m: usize, band: (f64, f64), fs: f64) -> Self {
+        let step = (band.1 - band.0) / m.max(1) as f64;
+        let w = Complex::from_polar(1., -2. * PI * step / fs);
+        let a = Complex::from_polar(1., 2. * PI * band.0 / fs);
+        Self::new(shape, axis, m, w, a)
+    }
+
+    /// Shape of the output, the size along `axis` is the number of points m
+    pub fn output_shape(&self) -> (usize, usize) {
+        if self.axis == 0 {
+            (self.m, self.cols)
+        } else {
+            (self.rows, self.m)
+        }
+    }
+
+    /// Number of elements of the output
+    pub fn output_len(&self) -> usize {
+        let (rows, cols) = self.output_shape();
+        rows * cols
+    }
+
+    /// Transform `input` of size `shape`, the result of size
+    /// [`Czt2d::output_shape`] is written to `output`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `input` or `output` do not have the planned sizes.
+    pub fn process(&mut self, input: &[Complex<f64>], output: &mut [Complex<f64>]) {
+        let (rows, cols, l) = (self.rows, self.cols, self.l);
+        assert!(
+            input.len() == rows * cols,
+            "{} != {}",
+            input.len(),
+            rows * cols
+        );
+        assert!(
+            output.len() == self.output_len(),
+            "{} != {}",
+            output.len(),
+            self.output_len()
+        );
+        if self.axis == 0 {
+            let pre = &self.pre;
+            for lane in self.buffer.chunks_exact_mut(l) {
+                lane[rows..].fill(Complex::default());
+            }
+            oop_transpose_strided_map(input, &mut self.buffer, rows, cols, cols, l, |x, r, _| {
+                x * pre[r]
+            });
+        } else {
+            for (src, dst) in input
+                .chunks_exact(cols)
+                .zip(self.buffer.chunks_exact_mut(l))
+            {
+                for ((y, x), p) in dst.iter_mut().zip(src).zip(&self.pre) {
+                    *y = x * p;
+                }
+                dst[cols..].fill(Complex::default());
+            }
+        }
+        self.fft
+            .process_with_scratch(&mut self.buffer, &mut self.scratch);
+        for lane in self.buffer.chunks_exact_mut(l) {
+            for (x, h) in lane.iter_mut().zip(&self.kernel) {
+                *x *= h;
+            }
+        }
+        self.ifft
+            .process_with_scratch(&mut self.buffer, &mut self.scratch);
+        if self.axis == 0 {
+            let post = &self.post;
+            oop_transpose_strided_map(&self.buffer, output, cols, self.m, l, cols, |x, _, k| {
+                x * post[k]
+            });
+        } else {
+            for (src, dst) in self
+                .buffer
+                .chunks_exact(l)
+                .zip(output.chunks_exact_mut(self.m))
+            {
+                for ((y, x), p) in dst.iter_mut().zip(src).zip(&self.post) {
+                    *y = x * p;
+                }
+            }
+        }
+    }
+}
+
+/// Frequencies of the points of [`Czt2d::zoom`]: f0 + k (f1 - f0) / m
+pub fn zoom_frequencies(m: usize, band: (f64, f64)) -> Vec<f64> {
+    let step = (band.1 - band.0) / m.max(1) as f64;
+    (0..m).map(|k| band.0 + k as f64 * step).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ndfft_with_transpose::fft2d_with_transpose;
+    use crate::test_array::{approx_eq, test_vec};
+
+    /// Direct evaluation of the chirp-Z transform
+    fn czt_direct(
+        v: &[Complex<f64>],
+        shape: (usize, usize),
+        axis: usize,
+        m: usize,
+        w: Complex<f64>,
+        a: Complex<f64>,
+    ) -> Vec<Complex<f64>> {
+        let (rows, cols) = shape;
+        let (out_rows, out_cols) = if axis == 0 { (m, cols) } else { (rows, m) };
+        let mut out = vec![Complex::default(); out_rows * out_cols];
+        for r in 0..out_rows {
+            for c in 0..out_cols {
+                let (k, n) = if axis == 0 { (r, rows) } else { (c, cols) };
+                out[r * out_cols + c] = (0..n)
+                    .map(|j| {
+                        let x = if axis == 0 {
+                            v[j * cols + c]
+                        } else {
+                            v[r * cols + j]
+                        };
+                        x * a.powi(-(j as i32)) * w.powi((j * k) as i32)
+                    })
+                    .sum();
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn test_czt() {
+        let shape = (12, 10);
+        let v = test_vec(shape.0 * shape.1);
+        // Spiral arc off the unit circle
+        let w = Complex::from_polar(1.01, -0.15);
+        let a = Complex::from_polar(0.98, 0.4);
+        for axis in 0..2 {
+            for m in [5, 17] {
+                let mut czt = Czt2d::new(shape, axis, m, w, a);
+                let mut result = vec![Complex::default(); czt.output_len()];
+                czt.process(&v, &mut result);
+                let expected = czt_direct(&v, shape, axis, m, w, a);
+                assert!(approx_eq(&result, &expected));
+            }
+        }
+    }
+
+    #[test]
+    fn test_zoom_full_band() {
+        // The full band [0, fs) with n points is the dft
+        let (rows, cols) = (9, 16);
+        let mut planner = FftPlanner::new();
+        let mut scratch = vec![Complex::default(); rows * cols + 64];
+        for (axis, n) in [(0, rows), (1, cols)] {
+            let v = test_vec(rows * cols);
+            let mut expected = v.clone();
+            let fft = planner.plan_fft_forward(n);
+            fft2d_with_transpose(&mut expected, &mut scratch, rows, cols, &fft, axis);
+            let mut czt = Czt2d::zoom((rows, cols), axis, n, (0., 2.), 2.);
+            let mut result = vec![Complex::default(); czt.output_len()];
+            czt.process(&v, &mut result);
+            assert!(approx_eq(&result, &expected));
+        }
+        assert!(zoom_frequencies(4, (1., 2.)) == vec![1., 1.25, 1.5, 1.75]);
+    }
+}
@@ -0,0 +1,372 @@
+//! Dealiasing of quadratic products in pseudo-spectral codes
+//!
+//! Two approaches are supported:
+//!
+//! - 2/3 rule: the spectrum is truncated to the modes |k| < N / 3 along each
+//!   axis, see [`dealias_mask`] and [`truncate_2_3`]
+//! - 3/2 rule: the spectra are zero-padded to 3m/2 x 3n/2 modes, multiplied
+//!   in physical space and truncated, see [`PaddedProduct`]
+//!
+//! All spectra are unnormalized forward ffts of the physical fields in
+//! natural layout, as returned by
+//! [`crate::ndfft_with_transpose::fft2d_full`] with
+//! [`Normalization::Backward`](crate::ndfft_with_transpose::Normalization::Backward).
+//! The Nyquist mode of an even number of points
+//! is dropped by the padded product.
+//!
+//! # Example
+//! ```
+//! use ndfft_test::dealias::PaddedProduct;
+//! use rustfft::num_complex::Complex;
+//!
+//! let (m, n) = (8, 6);
+//! let uhat = vec![Complex::new(1., 0.); m * n];
+//! let mut what = vec![Complex::new(0., 0.); m * n];
+//! let mut product = PaddedProduct::new((m, n));
+//! product.process(&uhat, &uhat, &mut what);
+//! ```
+use crate::ndfft_with_transpose::Layout;
+use crate::transpose::oop_transpose_strided_map;
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+use std::sync::Arc;
+
+/// Mask of the modes kept by the 2/3 rule, i.e. |k0| < m / 3 and |k1| < n / 3
+///
+/// The mask has m * n elements flattened in `layout`.
+pub fn dealias_mask(m: usize, n: usize, layout: Layout) -> Vec<bool> {
+    let keep0: Vec<bool> = (0..m).map(|i| 3 * mode_number(i, m) < m).collect();
+    let keep1: Vec<bool> = (0..n).map(|j| 3 * mode_number(j, n) < n).collect();
+    let (outer, inner) = match layout {
+        Layout::Natural => (keep0, keep1),
+        Layout::Transposed => (keep1, keep0),
+    };
+    outer
+        .iter()
+        .flat_map(|a| inner.iter().map(move |b| *a && *b))
+        .collect()
+}
+
+/// Zero all modes of the m x n spectrum `vhat` which are removed by the 2/3 rule
+///
+/// # Panics
+///
+/// Panics if `vhat` does not have m * n elements.
+pub fn truncate_2_3(vhat: &mut [Complex<f64>], m: usize, n: usize, layout: Layout) {
+    assert!(vhat.len() == m * n, "{} != {}", vhat.len(), m * n);
+    for (x, keep) in vhat.iter_mut().zip(dealias_mask(m, n, layout)) {
+        if !keep {
+            *x = Complex::default();
+        }
+    }
+}
+
+/// Absolute mode number of index `i` of a spectrum with `n` modes
+fn mode_number(i: usize, n: usize) -> usize {
+    if i < n.div_ceil(2) {
+        i
+    } else {
+        n - i
+    }
+}
+
+/// Dealiased product of two spectra, convenience wrapper of [`PaddedProduct`]
+///
+/// # Panics
+///
+/// Panics if m or n is 0, or `uhat` or `vhat` do not have m * n elements.
+pub fn padded_product(
+    uhat: &[Complex<f64>],
+    vhat: &[Complex<f64>],
+    m: usize,
+    n: usize,
+) -> Vec<Complex<f64>> {
+    let mut what = vec![Complex::default(); m * n];
+    PaddedProduct::new((m, n)).process(uhat, vhat, &mut what);
+    what
+}
+
+/// Reusable dealiased product of spectra on an m x n grid by 3/2 padding
+///
+/// The product is computed as follows:
+///
+/// - Pad each row of the spectra from n to 3n/2 modes and inverse fft
+///   along axis 1
+/// - Transpose, padding each column from m to 3m/2 modes on the fly, and
+///   inverse fft along the now contiguous axis 0
+/// - Multiply in physical space, still in transposed layout
+/// - Forward fft along axis 0, transpose back while truncating to m modes
+///   and scaling, forward fft along axis 1 and truncate each row to n modes
+pub struct PaddedProduct {
+    m: usize,
+    n: usize,
+    /// Padded shape
+    big_m: usize,
+    big_n: usize,
+    fft_m: Arc<dyn Fft<f64>>,
+    fft_n: Arc<dyn Fft<f64>>,
+    ifft_m: Arc<dyn Fft<f64>>,
+    ifft_n: Arc<dyn Fft<f64>>,
+    /// Row-padded m x 3n/2 work array
+    rows: Vec<Complex<f64>>,
+    /// Fully padded fields in transposed layout, i.e. 3n/2 x 3m/2
+    u_pad: Vec<Complex<f64>>,
+    v_pad: Vec<Complex<f64>>,
+    scratch: Vec<Complex<f64>>,
+}
+
+impl PaddedProduct {
+    /// Plan dealiased products of spectra of size `shape`
+    ///
+    /// # Panics
+    ///
+    /// Panics if m or n is 0.
+    pub fn new(shape: (usize, usize)) -> Self {
+        let (m, n) = shape;
+        assert!(m > 0 && n > 0, "shape must not be empty, got {:?}", shape);
+        let (big_m, big_n) = ((3 * m).div_ceil(2), (3 * n).div_ceil(2));
+        let mut planner = FftPlanner::<f64>::new();
+        let fft_m = planner.plan_fft_forward(big_m);
+        let fft_n = planner.plan_fft_forward(big_n);
+        let ifft_m = planner.plan_fft_inverse(big_m);
+        let ifft_n = planner.plan_fft_inverse(big_n);
+        let scratch_len = [&fft_m, &fft_n, &ifft_m, &ifft_n]
+            .iter()
+            .map(|p| p.get_inplace_scratch_len())
+            .max()
+            .unwrap_or(0);
+        Self {
+            m,
+            n,
+            big_m,
+            big_n,
+            fft_m,
+            fft_n,
+            ifft_m,
+            ifft_n,
+            rows: vec![Complex::default(); m * big_n],
+            u_pad: vec![Complex::default(); big_m * big_n],
+            v_pad: vec![Complex::default(); big_m * big_n],
+            scratch: vec![Complex::default(); scratch_len],
+        }
+    }
+
+    /// Shape of the padded grid
+    pub fn padded_shape(&self) -> (usize, usize) {
+        (self.big_m, self.big_n)
+    }
+
+    /// Spectrum of the product of the fields with spectra `uhat` and `vhat`,
+    /// written to `what`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `uhat`, `vhat` or `what` do not have m * n elements.
+    pub fn process(
+        &mut self,
+        uhat: &[Complex<f64>],
+        vhat: &[Complex<f64>],
+        what: &mut [Complex<f64>],
+    ) {
+        let (m, n, big_m, big_n) = (self.m, self.n, self.big_m, self.big_n);
+        for x in [uhat, vhat, &*what] {
+            assert!(x.len() == m * n, "{} != {}", x.len(), m * n);
+        }
+        for (xhat, pad) in [(uhat, &mut self.u_pad), (vhat, &mut self.v_pad)] {
+            for (src, dst) in xhat.chunks_exact(n).zip(self.rows.chunks_exact_mut(big_n)) {
+                pad_lane(src, dst);
+            }
+            self.ifft_n
+                .process_with_scratch(&mut self.rows, &mut self.scratch);
+            transpose_pad(&self.rows, pad, m, big_n, big_m);
+            self.ifft_m.process_with_scratch(pad, &mut self.scratch);
+        }
+        for (u, v) in self.u_pad.iter_mut().zip(self.v_pad.iter()) {
+            *u *= v;
+        }
+        self.fft_m
+            .process_with_scratch(&mut self.u_pad, &mut self.scratch);
+        // The inverse of the input and the forward transform on the padded
+        // grid are unnormalized
+        let scale = 1. / ((m * n) as f64 * (big_m * big_n) as f64);
+        transpose_truncate(&self.u_pad, &mut self.rows, m, big_n, big_m, scale);
+        self.fft_n
+            .process_with_scratch(&mut self.rows, &mut self.scratch);
+        for (src, dst) in self.rows.chunks_exact(big_n).zip(what.chunks_exact_mut(n)) {
+            truncate_lane(src, dst);
+        }
+    }
+}
+
+/// Number of non-negative and negative modes kept of a spectrum with `n`
+/// modes, the Nyquist mode of even `n` is dropped
+fn kept_modes(n: usize) -> (usize, usize) {
+    (n.div_ceil(2), (n.max(1) - 1) / 2)
+}
+
+/// Position of mode `i` of a spectrum with `n` modes in a padded spectrum with
+/// `big_n` modes, `None` for the dropped Nyquist mode
+fn padded_index(i: usize, n: usize, big_n: usize) -> Option<usize> {
+    let (p, q) = kept_modes(n);
+    if i < p {
+        Some(i)
+    } else if i >= n - q {
+        Some(i + big_n - n)
+    } else {
+        None
+    }
+}
+
+/// Zero-pad a spectrum `src` to the longer spectrum `dst`
+fn pad_lane(src: &[Complex<f64>], dst: &mut [Complex<f64>]) {
+    let (n, big_n) = (src.len(), dst.len());
+    let (p, q) = kept_modes(n);
+    dst[..p].copy_from_slice(&src[..p]);
+    dst[p..big_n - q].fill(Complex::default());
+    dst[big_n - q..].copy_from_slice(&src[n - q..]);
+}
+
+/// Truncate a spectrum `src` to the shorter spectrum `dst`
+fn truncate_lane(src: &[Complex<f64>], dst: &mut [Complex<f64>]) {
+    let (n, big_n) = (dst.len(), src.len());
+    let (p, q) = kept_modes(n);
+    dst[..p].copy_from_slice(&src[..p]);
+    dst[p..n - q].fill(Complex::default());
+    dst[n - q..].copy_from_slice(&src[big_n - q..]);
+}
+
+/// Transpose the rows x cols array `src` into the cols x `big_rows` array `dst`,
+/// zero-padding each row of `dst` from rows to `big_rows` modes
+fn transpose_pad(
+    src: &[Complex<f64>],
+    dst: &mut [Complex<f64>],
+    rows: usize,
+    cols: usize,
+    big_rows: usize,
+) {
+    let (p, q) = kept_modes(rows);
+    for lane in dst.chunks_exact_mut(big_rows) {
+        lane[p..big_rows - q].fill(Complex::default());
+    }
+    oop_transpose_strided_map(src, dst, p, cols, cols, big_rows, |x, _, _| x);
+    oop_transpose_strided_map(
+        &src[(rows - q) * cols..],
+        &mut dst[big_rows - q..],
+        q,
+        cols,
+        cols,
+        big_rows,
+        |x, _, _| x,
+    );
+}
+
+/// Transpose the cols x `big_rows` array `src` into the rows x cols array `dst`,
+/// truncating each row of `src` from `big_rows` to rows modes and scaling
+/// by `scale`
+fn transpose_truncate(
+    src: &[Complex<f64>],
+    dst: &mut [Complex<f64>],
+    rows: usize,
+    cols: usize,
+    big_rows: usize,
+    scale: f64,
+) {
+    let (p, q) = kept_modes(rows);
+    let f = |x: Complex<f64>, _, _| x * scale;
+    oop_transpose_strided_map(src, dst, cols, p, big_rows, cols, f);
+    dst[p * cols..(rows - q) * cols].fill(Complex::default());
+    oop_transpose_strided_map(
+        &src[big_rows - q..],
+        &mut dst[(rows - q) * cols..],
+        cols,
+        q,
+        big_rows,
+        cols,
+        f,
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ndfft_with_transpose::{fft2d_full, Normalization};
+    use crate::test_array::{approx_eq, grid};
+    use std::f64::consts::PI;
+
+    /// Fft along both axes of the real field `v`
+    fn fft(v: &[f64], m: usize, n: usize, norm: Normalization) -> Vec<Complex<f64>> {
+        let mut planner = FftPlanner::<f64>::new();
+        let fft_m = planner.plan_fft_forward(m);
+        let fft_n = planner.plan_fft_forward(n);
+        let extra = fft_m
+            .get_inplace_scratch_len()
+            .max(fft_n.get_inplace_scratch_len());
+        let mut scratch = vec![Complex::default(); m * n + extra];
+        let mut vhat: Vec<Complex<f64>> = v.iter().map(|x| Complex::new(*x, 0.)).collect();
+        fft2d_full(&mut vhat, &mut scratch, m, n, &fft_m, &fft_n, norm);
+        vhat
+    }
+
+    #[test]
+    fn test_dealias_mask() {
+        let mask = dealias_mask(6, 9, Layout::Natural);
+        // |k0| < 2 and |k1| < 3
+        let keep0 = [true, true, false, false, false, true];
+        let keep1 = [true, true, true, false, false, false, false, true, true];
+        for i in 0..6 {
+            for j in 0..9 {
+                assert!(mask[i * 9 + j] == (keep0[i] && keep1[j]));
+            }
+        }
+        let transposed = dealias_mask(6, 9, Layout::Transposed);
+        for i in 0..6 {
+            for j in 0..9 {
+                assert!(transposed[j * 6 + i] == mask[i * 9 + j]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_padded_product() {
+        for (m, n) in [(8, 8), (12, 9), (7, 10)] {
+            // Band-limited fields, whose product is aliased on the m x n grid
+            let u = |x: f64, y: f64| (3. * x).cos() + (2. * y).sin() + 0.5;
+            let v = |x: f64, y: f64| (2. * x).sin() * (3. * y).cos() + (x + y).cos();
+            let uhat = fft(
+                &grid(m, n, 2. * PI, 2. * PI, u),
+                m,
+                n,
+                Normalization::Backward,
+            );
+            let vhat = fft(
+                &grid(m, n, 2. * PI, 2. * PI, v),
+                m,
+                n,
+                Normalization::Backward,
+            );
+            let what = padded_product(&uhat, &vhat, m, n);
+
+            // Exact Fourier coefficients on a fine grid, truncated to m x n
+            let (fine_m, fine_n) = (4 * m, 4 * n);
+            let product = grid(fine_m, fine_n, 2. * PI, 2. * PI, |x, y| u(x, y) * v(x, y));
+            let product = fft(&product, fine_m, fine_n, Normalization::Forward);
+            let size = (m * n) as f64;
+            let mut expected = vec![Complex::default(); m * n];
+            for i in 0..m {
+                for j in 0..n {
+                    if let (Some(fi), Some(fj)) =
+                        (padded_index(i, m, fine_m), padded_index(j, n, fine_n))
+                    {
+                        expected[i * n + j] = product[fi * fine_n + fj] * size;
+                    }
+                }
+            }
+            assert!(approx_eq(&what, &expected));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "shape must not be empty")]
+    fn test_padded_product_empty() {
+        PaddedProduct::new((0, 4));
+    }
+}
@@ -0,0 +1,329 @@
+//! Spectral derivatives of periodic fields on flattened m x n grids
+//!
+//! The field is transformed along the differentiated axis, multiplied by
+//! (ik)^order and transformed back. Along axis 0 the spectrum is kept in
+//! transposed layout, see [`crate::ndfft_with_transpose::fft2d_with_layout`],
+//! which saves one transpose per transform.
+//!
+//! For odd orders the Nyquist mode of an even number of points is set to
+//! zero, since its derivative is not defined by the discrete data (the
+//! convention of most spectral codes).
+//!
+//! # Example
+//! ```
+//! use ndfft_test::derivative::SpectralDerivative;
+//! use std::f64::consts::PI;
+//!
+//! let (m, n) = (16, 8);
+//! let field: Vec<f64> = (0..m * n).map(|x| (2. * PI * (x / n) as f64 / m as f64).sin()).collect();
+//! let mut out = vec![0.; m * n];
+//! let mut derivative = SpectralDerivative::new((m, n), (2. * PI, 2. * PI));
+//! derivative.derivative(&field, &mut out, 0, 1);
+//! ```
+use crate::freq::{wavenumbers, Spectrum};
+use crate::ndfft_with_transpose::{
+    fft2d_full_with_layout, fft2d_with_layout, ifft2d_full_with_layout, ifft2d_with_layout, Layout,
+    Normalization,
+};
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+use std::sync::Arc;
+
+/// Derivative of order `order` along `axis` of a periodic m x n field
+///
+/// Convenience wrapper of [`SpectralDerivative`], which should be used
+/// for repeated derivatives.
+///
+/// # Arguments
+///
+/// * `field` - Flattened real 2D array with m * n elements
+/// * `shape` - Shape (m, n) of the field
+/// * `axis` - Axis of the derivative
+/// * `order` - Order of the derivative
+/// * `length` - Period of the field along `axis`
+///
+/// # Panics
+///
+/// Panics if `field` does not match `shape` or `axis` is not 0 or 1.
+pub fn spectral_derivative(
+    field: &[f64],
+    shape: (usize, usize),
+    axis: usize,
+    order: u32,
+    length: f64,
+) -> Vec<f64> {
+    let mut lengths = (1., 1.);
+    if axis == 0 {
+        lengths.0 = length;
+    } else {
+        lengths.1 = length;
+    }
+    let mut out = vec![0.; field.len()];
+    SpectralDerivative::new(shape, lengths).derivative(field, &mut out, axis, order);
+    out
+}
+
+/// Reusable spectral derivatives of periodic fields on an m x n grid
+///
+/// Owns the fft plans along both axes, the wavenumbers and a single scratch
+/// and work buffer shared by all operators.
+pub struct SpectralDerivative {
+    m: usize,
+    n: usize,
+    fft_m: Arc<dyn Fft<f64>>,
+    fft_n: Arc<dyn Fft<f64>>,
+    ifft_m: Arc<dyn Fft<f64>>,
+    ifft_n: Arc<dyn Fft<f64>>,
+    /// Wavenumbers along axis 0 and 1
+    k0: Vec<f64>,
+    k1: Vec<f64>,
+    scratch: Vec<Complex<f64>>,
+    buffer: Vec<Complex<f64>>,
+}
+
+impl SpectralDerivative {
+    /// Plan derivatives on a grid of size `shape` with periods `lengths`
+    pub fn new(shape: (usize, usize), lengths: (f64, f64)) -> Self {
+        let (m, n) = shape;
+        let mut planner = FftPlanner::<f64>::new();
+        let fft_m = planner.plan_fft_forward(m);
+        let fft_n = planner.plan_fft_forward(n);
+        let ifft_m = planner.plan_fft_inverse(m);
+        let ifft_n = planner.plan_fft_inverse(n);
+        let extra_len = [&fft_m, &fft_n, &ifft_m, &ifft_n]
+            .iter()
+            .map(|p| {
+                p.get_outofplace_scratch_len()
+                    .max(p.get_inplace_scratch_len())
+            })
+            .max()
+            .unwrap_or(0);
+        Self {
+            m,
+            n,
+            fft_m,
+            fft_n,
+            ifft_m,
+            ifft_n,
+            k0: wavenumbers(m, lengths.0, Spectrum::Full),
+            k1: wavenumbers(n, lengths.1, Spectrum::Full),
+            scratch: vec![Complex::default(); m * n + extra_len],
+            buffer: vec![Complex::default(); m * n],
+        }
+    }
+
+    /// Shape (m, n) of the grid
+    pub fn shape(&self) -> (usize, usize) {
+        (self.m, self.n)
+    }
+
+    /// Derivative of order `order` along `axis` of `field`, written to `out`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `field` or `out` do not have m * n elements or `axis`
+    /// is not 0 or 1.
+    pub fn derivative(&mut self, field: &[f64], out: &mut [f64], axis: usize, order: u32) {
+        assert!(axis < 2, "axis must be 0 or 1, got {}", axis);
+        let (m, n) = (self.m, self.n);
+        let (fft, ifft, k) = if axis == 0 {
+            (&self.fft_m, &self.ifft_m, &self.k0)
+        } else {
+            (&self.fft_n, &self.ifft_n, &self.k1)
+        };
+        let multiplier = derivative_multiplier(k, order);
+        load(field, &mut self.buffer);
+        let (norm, layout) = (Normalization::Backward, Layout::Transposed);
+        let layout = fft2d_with_layout(
+            &mut self.buffer,
+            &mut self.scratch,
+            m,
+            n,
+            fft,
+            axis,
+            norm,
+            layout,
+        );
+        // Lanes along `axis` are contiguous in both layouts
+        for lane in self.buffer.chunks_exact_mut(k.len()) {
+            for (x, d) in lane.iter_mut().zip(multiplier.iter()) {
+                *x *= d;
+            }
+        }
+        ifft2d_with_layout(
+            &mut self.buffer,
+            &mut self.scratch,
+            m,
+            n,
+            ifft,
+            axis,
+            norm,
+            layout,
+        );
+        store(&self.buffer, out);
+    }
+
+    /// Gradient of `field`, the derivatives along axis 0 and 1 are written
+    /// to `out0` and `out1`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `field`, `out0` or `out1` do not have m * n elements.
+    pub fn gradient(&mut self, field: &[f64], out0: &mut [f64], out1: &mut [f64]) {
+        self.derivative(field, out0, 0, 1);
+        self.derivative(field, out1, 1, 1);
+    }
+
+    /// Laplacian of `field`, written to `out`
+    ///
+    /// Both axes are transformed at once and the spectrum is multiplied by
+    /// -(k0^2 + k1^2).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `field` or `out` do not have m * n elements.
+    pub fn laplacian(&mut self, field: &[f64], out: &mut [f64]) {
+        let (m, n) = (self.m, self.n);
+        load(field, &mut self.buffer);
+        let (norm, layout) = (Normalization::Backward, Layout::Transposed);
+        let (fft_m, fft_n) = (&self.fft_m, &self.fft_n);
+        fft2d_full_with_layout(
+            &mut self.buffer,
+            &mut self.scratch,
+            m,
+            n,
+            fft_m,
+            fft_n,
+            norm,
+            layout,
+        );
+        // Transposed spectrum, i.e. n x m
+        for (lane, k1) in self.buffer.chunks_exact_mut(m).zip(self.k1.iter()) {
+            for (x, k0) in lane.iter_mut().zip(self.k0.iter()) {
+                *x *= -(k0 * k0 + k1 * k1);
+            }
+        }
+        let (ifft_m, ifft_n) = (&self.ifft_m, &self.ifft_n);
+        ifft2d_full_with_layout(
+            &mut self.buffer,
+            &mut self.scratch,
+            m,
+            n,
+            ifft_m,
+            ifft_n,
+            norm,
+            layout,
+        );
+        store(&self.buffer, out);
+    }
+}
+
+/// Multiplier (ik)^order of each mode, zero for the Nyquist mode of odd orders
+fn derivative_multiplier(k: &[f64], order: u32) -> Vec<Complex<f64>> {
+    let len = k.len();
+    let mut multiplier: Vec<Complex<f64>> =
+        k.iter().map(|&k| Complex::new(0., k).powu(order)).collect();
+    if order % 2 == 1 && len.is_multiple_of(2) {
+        multiplier[len / 2] = Complex::default();
+    }
+    multiplier
+}
+
+/// Copy a real field into the complex work buffer
+fn load(field: &[f64], buffer: &mut [Complex<f64>]) {
+    assert!(
+        field.len() == buffer.len(),
+        "{} != {}",
+        field.len(),
+        buffer.len()
+    );
+    for (y, x) in buffer.iter_mut().zip(field.iter()) {
+        *y = Complex::new(*x, 0.);
+    }
+}
+
+/// Copy the real part of the complex work buffer into `out`
+fn store(buffer: &[Complex<f64>], out: &mut [f64]) {
+    assert!(
+        out.len() == buffer.len(),
+        "{} != {}",
+        out.len(),
+        buffer.len()
+    );
+    for (y, x) in out.iter_mut().zip(buffer.iter()) {
+        *y = x.re;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_array::grid;
+    use std::f64::consts::PI;
+
+    fn assert_approx_eq(result: &[f64], expected: &[f64]) {
+        let scale = expected.iter().fold(1., |a: f64, b| a.max(b.abs()));
+        assert!(result.len() == expected.len());
+        for (a, b) in result.iter().zip(expected.iter()) {
+            assert!((a - b).abs() < 1e-10 * scale, "{} != {}", a, b);
+        }
+    }
+
+    #[test]
+    fn test_derivative() {
+        let (m, n) = (32, 18);
+        let (lx, ly) = (2. * PI, 4.);
+        let (a, b) = (2., 2. * PI * 3. / ly);
+        let field = grid(m, n, lx, ly, |x, y| (a * x).sin() * (b * y).cos());
+        let mut derivative = SpectralDerivative::new((m, n), (lx, ly));
+        let mut out = vec![0.; m * n];
+
+        derivative.derivative(&field, &mut out, 0, 1);
+        let expected = grid(m, n, lx, ly, |x, y| a * (a * x).cos() * (b * y).cos());
+        assert_approx_eq(&out, &expected);
+
+        derivative.derivative(&field, &mut out, 1, 2);
+        let expected = grid(m, n, lx, ly, |x, y| -b * b * (a * x).sin() * (b * y).cos());
+        assert_approx_eq(&out, &expected);
+
+        derivative.derivative(&field, &mut out, 0, 3);
+        let expected = grid(m, n, lx, ly, |x, y| {
+            -a * a * a * (a * x).cos() * (b * y).cos()
+        });
+        assert_approx_eq(&out, &expected);
+
+        let out = spectral_derivative(&field, (m, n), 1, 1, ly);
+        let expected = grid(m, n, lx, ly, |x, y| -b * (a * x).sin() * (b * y).sin());
+        assert_approx_eq(&out, &expected);
+    }
+
+    #[test]
+    fn test_gradient_laplacian() {
+        let (m, n) = (24, 20);
+        let (lx, ly) = (2. * PI, 2. * PI);
+        let field = grid(m, n, lx, ly, |x, y| (2. * x).sin() * (3. * y).cos());
+        let mut derivative = SpectralDerivative::new((m, n), (lx, ly));
+
+        let (mut out0, mut out1) = (vec![0.; m * n], vec![0.; m * n]);
+        derivative.gradient(&field, &mut out0, &mut out1);
+        let expected = grid(m, n, lx, ly, |x, y| 2. * (2. * x).cos() * (3. * y).cos());
+        assert_approx_eq(&out0, &expected);
+        let expected = grid(m, n, lx, ly, |x, y| -3. * (2. * x).sin() * (3. * y).sin());
+        assert_approx_eq(&out1, &expected);
+
+        derivative.laplacian(&field, &mut out0);
+        let expected: Vec<f64> = field.iter().map(|x| -13. * x).collect();
+        assert_approx_eq(&out0, &expected);
+    }
+
+    #[test]
+    fn test_nyquist() {
+        // The Nyquist mode has no first, but a second derivative
+        let (m, n) = (8, 6);
+        let field = grid(m, n, 2. * PI, 2. * PI, |_, y| (3. * y).cos());
+        let out = spectral_derivative(&field, (m, n), 1, 1, 2. * PI);
+        assert_approx_eq(&out, &vec![0.; m * n]);
+        let out = spectral_derivative(&field, (m, n), 1, 2, 2. * PI);
+        let expected: Vec<f64> = field.iter().map(|x| -9. * x).collect();
+        assert_approx_eq(&out, &expected);
+    }
+}
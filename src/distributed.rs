@@ -0,0 +1,640 @@
+//! Distributed 3D fft with a pencil decomposition
+//!
+//! The n0 x n1 x n2 array is split along axes 0 and 1 over a p_row x p_col
+//! grid of workers, each worker owns a pencil with the full axis 2. The worker
+//! in grid row r communicates with the workers of its grid row and of its
+//! grid column. The forward transform
+//!
+//! 1. transforms the contiguous axis 2 with [`fft2d_with_transpose_norm`]
+//! 2. redistributes the data within the grid row, such that axis 1 is local
+//!    and axis 2 is split over the p_col workers of the row
+//! 3. transforms axis 1
+//! 4. redistributes the data within the grid column, such that axis 0 is
+//!    local and axis 1 is split over the p_row workers of the column
+//! 5. transforms axis 0
+//!
+//! The spectrum stays distributed, with local layout (k1, k0, k2), which
+//! saves a third exchange (like the transposed output of FFTW's MPI
+//! interface). The inverse transform reverses these steps.
+//!
+//! Up to min(n0, n1) x min(n1, n2) workers receive data. A slab
+//! decomposition, i.e. a grid with a single column, skips the first exchange,
+//! see [`PencilFft3d::slab`].
+//!
+//! The exchange is abstracted by the [`AllToAll`] trait. [`ChannelAllToAll`]
+//! connects threads of a single process by channels, e.g. for testing; a
+//! message passing (MPI) backend can implement the same trait.
+//!
+//! # Example
+//! ```
+//! use ndfft_test::distributed::{ChannelAllToAll, PencilFft3d};
+//! use rustfft::num_complex::Complex;
+//!
+//! let shape = [8, 6, 4];
+//! std::thread::scope(|s| {
+//!     for (row, col) in ChannelAllToAll::grid(2, 2) {
+//!         s.spawn(move || {
+//!             let mut fft = PencilFft3d::new(shape, row, col);
+//!             let mut v = vec![Complex::new(1., 0.); fft.input_len()];
+//!             let mut vhat = vec![Complex::new(0., 0.); fft.output_len()];
+//!             fft.forward(&mut v, &mut vhat);
+//!             fft.backward(&mut vhat, &mut v);
+//!         });
+//!     }
+//! });
+//! ```
+use crate::ndfft_with_transpose::{
+    fft2d_with_transpose_norm, ifft2d_with_transpose, Normalization,
+};
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+use std::ops::Range;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+
+/// Collective all-to-all exchange between a group of workers
+///
+/// Modelled after `MPI_Alltoallv`: all workers of the group call
+/// [`AllToAll::all_to_all`] collectively.
+pub trait AllToAll<T> {
+    /// Index of this worker in the group
+    fn rank(&self) -> usize;
+
+    /// Number of workers in the group
+    fn size(&self) -> usize;
+
+    /// Send `send_counts[q]` consecutive elements of `send` to worker q and
+    /// receive `recv_counts[p]` consecutive elements of `recv` from worker p,
+    /// both ordered by rank
+    fn all_to_all(
+        &mut self,
+        send: &[T],
+        send_counts: &[usize],
+        recv: &mut [T],
+        recv_counts: &[usize],
+    );
+}
+
+/// In-process [`AllToAll`] between threads, connected by channels
+pub struct ChannelAllToAll<T> {
+    rank: usize,
+    /// Sender to each worker
+    senders: Vec<Sender<Vec<T>>>,
+    /// Receiver from each worker
+    receivers: Vec<Receiver<Vec<T>>>,
+}
+
+impl<T> ChannelAllToAll<T> {
+    /// Connected group of `size` workers, the i-th element has rank i
+    pub fn group(size: usize) -> Vec<Self> {
+        // channels[p][q] connects worker p to worker q
+        let mut senders: Vec<Vec<Sender<Vec<T>>>> = (0..size).map(|_| vec![]).collect();
+        let mut receivers: Vec<Vec<Receiver<Vec<T>>>> = (0..size).map(|_| vec![]).collect();
+        for sender in &mut senders {
+            for receiver in &mut receivers {
+                let (tx, rx) = channel();
+                sender.push(tx);
+                receiver.push(rx);
+            }
+        }
+        senders
+            .into_iter()
+            .zip(receivers)
+            .enumerate()
+            .map(|(rank, (senders, receivers))| Self {
+                rank,
+                senders,
+                receivers,
+            })
+            .collect()
+    }
+
+    /// Connected p_row x p_col grid of workers
+    ///
+    /// The element of worker (r, c) is at index r * p_col + c and holds the
+    /// group of its grid row (rank c) and the group of its grid column
+    /// (rank r), as expected by [`PencilFft3d::new`].
+    pub fn grid(p_row: usize, p_col: usize) -> Vec<(Self, Self)> {
+        let mut rows: Vec<_> = (0..p_row).map(|_| Self::group(p_col).into_iter()).collect();
+        let mut cols: Vec<_> = (0..p_col).map(|_| Self::group(p_row).into_iter()).collect();
+        let mut grid = Vec::with_capacity(p_row * p_col);
+        for row in &mut rows {
+            for col in &mut cols {
+                grid.push((row.next().unwrap(), col.next().unwrap()));
+            }
+        }
+        grid
+    }
+}
+
+impl<T: Copy + Send> AllToAll<T> for ChannelAllToAll<T> {
+    fn rank(&self) -> usize {
+        self.rank
+    }
+
+    fn size(&self) -> usize {
+        self.senders.len()
+    }
+
+    fn all_to_all(
+        &mut self,
+        send: &[T],
+        send_counts: &[usize],
+        recv: &mut [T],
+        recv_counts: &[usize],
+    ) {
+        let size = self.size();
+        assert!(
+            send_counts.len() == size,
+            "{} != {}",
+            send_counts.len(),
+            size
+        );
+        assert!(
+            recv_counts.len() == size,
+            "{} != {}",
+            recv_counts.len(),
+            size
+        );
+        let mut offset = 0;
+        for (sender, count) in self.senders.iter().zip(send_counts) {
+            sender
+                .send(send[offset..offset + count].to_vec())
+                .expect("worker of the group disconnected");
+            offset += count;
+        }
+        // Messages of each pair arrive in order, so consecutive exchanges
+        // cannot mix
+        let mut offset = 0;
+        for (receiver, count) in self.receivers.iter().zip(recv_counts) {
+            let data = receiver.recv().expect("worker of the group disconnected");
+            assert!(data.len() == *count, "{} != {}", data.len(), count);
+            recv[offset..offset + count].copy_from_slice(&data);
+            offset += count;
+        }
+    }
+}
+
+/// Range of `n` elements owned by worker `rank` of `size` workers
+fn partition(n: usize, size: usize, rank: usize) -> Range<usize> {
+    n * rank / size..n * (rank + 1) / size
+}
+
+/// Ranges along axes 1 and 2 of a block of a 3D array, axis 0 is complete
+type Block = [Range<usize>; 2];
+
+/// Exchange blocks of 3D arrays between all workers of `comm`
+///
+/// Worker q receives the block `send_blocks[q]` of `src`, which has shape
+/// `src_shape`. The block received from worker p is stored at
+/// `recv_blocks[p]` of `dst`, which has shape `dst_shape`. If `swap`, the
+/// blocks arrive with axes 0 and 1 swapped, i.e. the sender's axis 0 is the
+/// receiver's axis 1.
+#[allow(clippy::too_many_arguments)]
+fn exchange<C: AllToAll<Complex<f64>>>(
+    comm: &mut C,
+    (send, recv): (&mut [Complex<f64>], &mut [Complex<f64>]),
+    src: &[Complex<f64>],
+    src_shape: [usize; 3],
+    send_blocks: &[Block],
+    dst: &mut [Complex<f64>],
+    dst_shape: [usize; 3],
+    recv_blocks: &[Block],
+    swap: bool,
+) {
+    let [s0, s1, s2] = src_shape;
+    let [d0, d1, d2] = dst_shape;
+    let count = |n0: usize, [r1, r2]: &Block| n0 * r1.len() * r2.len();
+    let send_counts: Vec<usize> = send_blocks.iter().map(|b| count(s0, b)).collect();
+    let recv_counts: Vec<usize> = recv_blocks.iter().map(|b| count(d0, b)).collect();
+    // Pack the blocks contiguously, in the order of the workers
+    let mut offset = 0;
+    for [r1, r2] in send_blocks {
+        for i in 0..s0 {
+            for j in r1.clone() {
+                let line = &src[(i * s1 + j) * s2 + r2.start..(i * s1 + j) * s2 + r2.end];
+                send[offset..offset + line.len()].copy_from_slice(line);
+                offset += line.len();
+            }
+        }
+    }
+    comm.all_to_all(
+        &send[..offset],
+        &send_counts,
+        &mut recv[..dst.len()],
+        &recv_counts,
+    );
+    let mut offset = 0;
+    for [r1, r2] in recv_blocks {
+        let len = r2.len();
+        let mut unpack = |i: usize, j: usize| {
+            let start = (i * d1 + j) * d2 + r2.start;
+            dst[start..start + len].copy_from_slice(&recv[offset..offset + len]);
+            offset += len;
+        };
+        if swap {
+            for j in r1.clone() {
+                for i in 0..d0 {
+                    unpack(i, j);
+                }
+            }
+        } else {
+            for i in 0..d0 {
+                for j in r1.clone() {
+                    unpack(i, j);
+                }
+            }
+        }
+    }
+}
+
+/// 3D fft of an array distributed in pencils over a 2D grid of workers
+pub struct PencilFft3d<C: AllToAll<Complex<f64>>> {
+    shape: [usize; 3],
+    /// Workers of the grid row, `None` for a grid with a single column
+    row: Option<C>,
+    /// Workers of the grid column
+    col: C,
+    norm: Normalization,
+    fft: [Arc<dyn Fft<f64>>; 3],
+    ifft: [Arc<dyn Fft<f64>>; 3],
+    scratch: Vec<Complex<f64>>,
+    /// Pencil between the exchanges, with axis 1 local
+    work: Vec<Complex<f64>>,
+    /// Packed data of the exchanges
+    send: Vec<Complex<f64>>,
+    recv: Vec<Complex<f64>>,
+}
+
+impl<C: AllToAll<Complex<f64>>> PencilFft3d<C> {
+    /// Plan a distributed 3D fft of an array of size `shape` on a grid of
+    /// workers
+    ///
+    /// # Arguments
+    ///
+    /// * `shape` - Global shape of the array
+    /// * `row` - Workers of the grid row of this worker, its rank is the
+    ///   grid column
+    /// * `col` - Workers of the grid column of this worker, its rank is the
+    ///   grid row
+    pub fn new(shape: [usize; 3], row: C, col: C) -> Self {
+        Self::with_groups(shape, Some(row), col)
+    }
+
+    /// Plan a distributed 3D fft of an array of size `shape`, split along
+    /// axis 0 over the workers of `comm`
+    ///
+    /// This is a grid with a single column, which needs only the exchange
+    /// within the column.
+    pub fn slab(shape: [usize; 3], comm: C) -> Self {
+        Self::with_groups(shape, None, comm)
+    }
+
+    fn with_groups(shape: [usize; 3], row: Option<C>, col: C) -> Self {
+        let mut planner = FftPlanner::new();
+        let fft = shape.map(|n| planner.plan_fft_forward(n));
+        let ifft = shape.map(|n| planner.plan_fft_inverse(n));
+        let mut plan = Self {
+            shape,
+            row,
+            col,
+            norm: Normalization::default(),
+            fft,
+            ifft,
+            scratch: vec![],
+            work: vec![],
+            send: vec![],
+            recv: vec![],
+        };
+        let [n0, n1, _] = shape;
+        let [_, _, r2] = plan.output_ranges();
+        let inplace = |i: usize| {
+            plan.fft[i]
+                .get_inplace_scratch_len()
+                .max(plan.ifft[i].get_inplace_scratch_len())
+        };
+        // Planes along axes 1 and 0 are transposed, axis 2 is contiguous
+        let scratch_len = (n0 * r2.len() + inplace(0))
+            .max(n1 * r2.len() + inplace(1))
+            .max(inplace(2));
+        let work_len = if plan.row.is_some() {
+            plan.input_ranges()[0].len() * n1 * r2.len()
+        } else {
+            0
+        };
+        let buffer_len = plan.input_len().max(work_len).max(plan.output_len());
+        plan.scratch = vec![Complex::default(); scratch_len];
+        plan.work = vec![Complex::default(); work_len];
+        plan.send = vec![Complex::default(); buffer_len];
+        plan.recv = plan.send.clone();
+        plan
+    }
+
+    /// Set the normalization, default is [`Normalization::Backward`]
+    #[must_use]
+    pub fn normalization(mut self, norm: Normalization) -> Self {
+        self.norm = norm;
+        self
+    }
+
+    /// Global shape of the array
+    pub fn shape(&self) -> [usize; 3] {
+        self.shape
+    }
+
+    /// Shape (p_row, p_col) of the worker grid
+    pub fn grid(&self) -> (usize, usize) {
+        (
+            self.col.size(),
+            self.row.as_ref().map_or(1, |row| row.size()),
+        )
+    }
+
+    /// Position (r, c) of this worker in the grid
+    fn position(&self) -> (usize, usize) {
+        (
+            self.col.rank(),
+            self.row.as_ref().map_or(0, |row| row.rank()),
+        )
+    }
+
+    /// Ranges along the axes of the physical array owned by this worker,
+    /// the local pencil has layout (i0, i1, i2)
+    pub fn input_ranges(&self) -> [Range<usize>; 3] {
+        let [n0, n1, n2] = self.shape;
+        let ((p_row, p_col), (r, c)) = (self.grid(), self.position());
+        [partition(n0, p_row, r), partition(n1, p_col, c), 0..n2]
+    }
+
+    /// Ranges along the axes of the spectrum owned by this worker, the local
+    /// pencil has layout (k1, k0, k2)
+    pub fn output_ranges(&self) -> [Range<usize>; 3] {
+        let [n0, n1, n2] = self.shape;
+        let ((p_row, p_col), (r, c)) = (self.grid(), self.position());
+        [0..n0, partition(n1, p_row, r), partition(n2, p_col, c)]
+    }
+
+    /// Number of elements of the local physical pencil
+    pub fn input_len(&self) -> usize {
+        self.input_ranges().iter().map(|r| r.len()).product()
+    }
+
+    /// Number of elements of the local spectrum pencil
+    pub fn output_len(&self) -> usize {
+        self.output_ranges().iter().map(|r| r.len()).product()
+    }
+
+    /// Forward transform, collective over all workers
+    ///
+    /// # Arguments
+    ///
+    /// * `v` - Local physical pencil (l0, l1, n2), overwritten
+    /// * `vhat` - Local spectrum pencil (m1, n0, m2), output
+    ///
+    /// # Panics
+    ///
+    /// Panics if `v` or `vhat` do not match the local pencils.
+    pub fn forward(&mut self, v: &mut [Complex<f64>], vhat: &mut [Complex<f64>]) {
+        self.assert_lengths(v, vhat);
+        let [x, y, z] = self.pencil_shapes();
+        let [x_blocks, y_row_blocks, y_col_blocks, z_blocks] = self.blocks();
+        let Self {
+            row,
+            col,
+            norm,
+            fft,
+            scratch,
+            work,
+            send,
+            recv,
+            ..
+        } = self;
+        let norm = *norm;
+        fft2d_with_transpose_norm(v, scratch, x[0] * x[1], x[2], &fft[2], 1, norm);
+        let pencil = match row {
+            Some(row) => {
+                let buffers = (&mut send[..], &mut recv[..]);
+                exchange(row, buffers, v, x, &x_blocks, work, y, &y_row_blocks, false);
+                &mut work[..]
+            }
+            None => v,
+        };
+        for plane in planes(pencil, y[1] * y[2]) {
+            fft2d_with_transpose_norm(plane, scratch, y[1], y[2], &fft[1], 0, norm);
+        }
+        let buffers = (&mut send[..], &mut recv[..]);
+        exchange(
+            col,
+            buffers,
+            pencil,
+            y,
+            &y_col_blocks,
+            vhat,
+            z,
+            &z_blocks,
+            true,
+        );
+        for plane in planes(vhat, z[1] * z[2]) {
+            fft2d_with_transpose_norm(plane, scratch, z[1], z[2], &fft[0], 0, norm);
+        }
+    }
+
+    /// Inverse transform, collective over all workers
+    ///
+    /// # Arguments
+    ///
+    /// * `vhat` - Local spectrum pencil (m1, n0, m2), overwritten
+    /// * `v` - Local physical pencil (l0, l1, n2), output
+    ///
+    /// # Panics
+    ///
+    /// Panics if `v` or `vhat` do not match the local pencils.
+    pub fn backward(&mut self, vhat: &mut [Complex<f64>], v: &mut [Complex<f64>]) {
+        self.assert_lengths(v, vhat);
+        let [x, y, z] = self.pencil_shapes();
+        let [x_blocks, y_row_blocks, y_col_blocks, z_blocks] = self.blocks();
+        let Self {
+            row,
+            col,
+            norm,
+            ifft,
+            scratch,
+            work,
+            send,
+            recv,
+            ..
+        } = self;
+        let norm = *norm;
+        for plane in planes(vhat, z[1] * z[2]) {
+            ifft2d_with_transpose(plane, scratch, z[1], z[2], &ifft[0], 0, norm);
+        }
+        let pencil = if row.is_some() {
+            &mut work[..]
+        } else {
+            &mut *v
+        };
+        let buffers = (&mut send[..], &mut recv[..]);
+        exchange(
+            col,
+            buffers,
+            vhat,
+            z,
+            &z_blocks,
+            pencil,
+            y,
+            &y_col_blocks,
+            true,
+        );
+        for plane in planes(pencil, y[1] * y[2]) {
+            ifft2d_with_transpose(plane, scratch, y[1], y[2], &ifft[1], 0, norm);
+        }
+        if let Some(row) = row {
+            let buffers = (&mut send[..], &mut recv[..]);
+            exchange(row, buffers, work, y, &y_row_blocks, v, x, &x_blocks, false);
+        }
+        ifft2d_with_transpose(v, scratch, x[0] * x[1], x[2], &ifft[2], 1, norm);
+    }
+
+    fn assert_lengths(&self, v: &[Complex<f64>], vhat: &[Complex<f64>]) {
+        assert!(
+            v.len() == self.input_len(),
+            "{} != {}",
+            v.len(),
+            self.input_len()
+        );
+        assert!(
+            vhat.len() == self.output_len(),
+            "{} != {}",
+            vhat.len(),
+            self.output_len()
+        );
+    }
+
+    /// Local shapes of the physical pencil (l0, l1, n2), of the pencil
+    /// between the exchanges (l0, n1, m2) and of the spectrum (m1, n0, m2)
+    fn pencil_shapes(&self) -> [[usize; 3]; 3] {
+        let [n0, n1, n2] = self.shape;
+        let [r0, r1, _] = self.input_ranges();
+        let [_, s1, s2] = self.output_ranges();
+        [
+            [r0.len(), r1.len(), n2],
+            [r0.len(), n1, s2.len()],
+            [s1.len(), n0, s2.len()],
+        ]
+    }
+
+    /// Blocks of the exchanges for each worker: of the physical pencil and
+    /// the middle pencil within the grid row, and of the middle pencil and
+    /// the spectrum within the grid column
+    fn blocks(&self) -> [Vec<Block>; 4] {
+        let [n0, n1, n2] = self.shape;
+        let (p_row, p_col) = self.grid();
+        let [[_, l1, _], [_, _, m2], _] = self.pencil_shapes();
+        [
+            (0..p_col)
+                .map(|q| [0..l1, partition(n2, p_col, q)])
+                .collect(),
+            (0..p_col)
+                .map(|q| [partition(n1, p_col, q), 0..m2])
+                .collect(),
+            (0..p_row)
+                .map(|q| [partition(n1, p_row, q), 0..m2])
+                .collect(),
+            (0..p_row)
+                .map(|q| [partition(n0, p_row, q), 0..m2])
+                .collect(),
+        ]
+    }
+}
+
+/// Consecutive planes of `len` elements, none if the planes are empty
+fn planes(data: &mut [Complex<f64>], len: usize) -> std::slice::ChunksExactMut<'_, Complex<f64>> {
+    // chunks_exact_mut panics for a chunk size of 0
+    data.chunks_exact_mut(len.max(1))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ndfft_with_transpose::fftnd_with_transpose;
+    use crate::test_array::{approx_eq, test_vec};
+
+    type Plan = PencilFft3d<ChannelAllToAll<Complex<f64>>>;
+
+    /// Round trip of `plans` of all workers, compared with the serial fft
+    fn check_plans(shape: [usize; 3], plans: Vec<Plan>) {
+        let [n0, n1, n2] = shape;
+        let v = test_vec(n0 * n1 * n2);
+        let mut expected = v.clone();
+        let mut planner = FftPlanner::new();
+        for (axis, n) in shape.iter().enumerate() {
+            let fft = planner.plan_fft_forward(*n);
+            let mut scratch = vec![Complex::default(); v.len() + fft.get_inplace_scratch_len()];
+            let norm = Normalization::Backward;
+            fftnd_with_transpose(&mut expected, &mut scratch, &shape, &fft, axis, norm);
+        }
+        let mut result = vec![Complex::default(); v.len()];
+        std::thread::scope(|s| {
+            let handles: Vec<_> = plans
+                .into_iter()
+                .map(|mut fft| {
+                    let v = &v;
+                    s.spawn(move || {
+                        let [r0, r1, _] = fft.input_ranges();
+                        let original: Vec<_> = r0
+                            .flat_map(|i| {
+                                let start = i * n1 * n2;
+                                v[start + r1.start * n2..start + r1.end * n2].to_vec()
+                            })
+                            .collect();
+                        let mut local = original.clone();
+                        let mut vhat = vec![Complex::default(); fft.output_len()];
+                        fft.forward(&mut local, &mut vhat);
+                        let spectrum = vhat.clone();
+                        fft.backward(&mut vhat, &mut local);
+                        assert!(approx_eq(&local, &original));
+                        (fft.output_ranges(), spectrum)
+                    })
+                })
+                .collect();
+            // Gather the spectra with local layout (k1, k0, k2)
+            for handle in handles {
+                let ([_, s1, s2], spectrum) = handle.join().unwrap();
+                for (j, plane) in s1.zip(spectrum.chunks_exact((n0 * s2.len()).max(1))) {
+                    for (i, line) in plane.chunks_exact(s2.len().max(1)).enumerate() {
+                        let start = (i * n1 + j) * n2 + s2.start;
+                        result[start..start + s2.len()].copy_from_slice(line);
+                    }
+                }
+            }
+        });
+        assert!(approx_eq(&result, &expected));
+    }
+
+    #[test]
+    fn test_slab_fft3d() {
+        // [509, 3, 2] needs more fft scratch than a plane along axis 0
+        for shape in [[7, 6, 5], [509, 3, 2]] {
+            for size in [1, 2, 4] {
+                let plans = ChannelAllToAll::group(size)
+                    .into_iter()
+                    .map(|comm| PencilFft3d::slab(shape, comm))
+                    .collect();
+                check_plans(shape, plans);
+            }
+        }
+    }
+
+    #[test]
+    fn test_pencil_fft3d() {
+        for shape in [[7, 6, 5], [509, 3, 2]] {
+            for (p_row, p_col) in [(1, 1), (2, 1), (1, 2), (2, 2), (2, 3)] {
+                let plans: Vec<Plan> = ChannelAllToAll::grid(p_row, p_col)
+                    .into_iter()
+                    .map(|(row, col)| PencilFft3d::new(shape, row, col))
+                    .collect();
+                assert!(plans[0].grid() == (p_row, p_col));
+                check_plans(shape, plans);
+            }
+        }
+    }
+}
@@ -0,0 +1,320 @@
+//! Sample frequencies and wavenumber meshes of discrete Fourier transforms
+//!
+//! The frequencies match the output ordering of the transforms of this crate
+//! and of numpy: [`Spectrum::Full`] for complex ffts, [`Spectrum::Half`] for the
+//! axis transformed by a real-to-complex fft and [`Spectrum::Shifted`] after an
+//! fftshift (see [`crate::shift`]). The 2D meshes are flattened in the
+//! [`Layout`] of the transformed data.
+//!
+//! # Example
+//! ```
+//! use ndfft_test::freq::{fftfreq, rfftfreq};
+//!
+//! assert!(fftfreq(4, 1.) == vec![0., 0.25, -0.5, -0.25]);
+//! assert!(rfftfreq(4, 1.) == vec![0., 0.25, 0.5]);
+//! ```
+use crate::ndfft_with_transpose::Layout;
+use std::f64::consts::PI;
+
+/// Ordering of the frequencies along one axis of a spectrum
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Spectrum {
+    /// n frequencies of a complex fft, see [`fftfreq`]
+    #[default]
+    Full,
+    /// n frequencies of a complex fft after an fftshift
+    Shifted,
+    /// n / 2 + 1 non-negative frequencies of a real-to-complex fft,
+    /// see [`rfftfreq`]
+    Half,
+}
+
+impl Spectrum {
+    /// Number of frequencies of an axis with `n` points
+    pub fn len(self, n: usize) -> usize {
+        match self {
+            Self::Full | Self::Shifted => n,
+            Self::Half => n / 2 + 1,
+        }
+    }
+}
+
+/// Sample frequencies of a complex fft of length `n` with sample spacing `d`
+///
+/// Same as numpy's `fftfreq`: [0, 1, ..., (n - 1) / 2, -(n / 2), ..., -1] / (d * n)
+pub fn fftfreq(n: usize, d: f64) -> Vec<f64> {
+    let scale = 1. / (d * n as f64);
+    (0..n)
+        .map(|i| {
+            let k = if i < n.div_ceil(2) {
+                i as isize
+            } else {
+                i as isize - n as isize
+            };
+            k as f64 * scale
+        })
+        .collect()
+}
+
+/// Sample frequencies of a real-to-complex fft of length `n` with sample
+/// spacing `d`
+///
+/// Same as numpy's `rfftfreq`: [0, 1, ..., n / 2] / (d * n)
+pub fn rfftfreq(n: usize, d: f64) -> Vec<f64> {
+    let scale = 1. / (d * n as f64);
+    (0..=n / 2).map(|i| i as f64 * scale).collect()
+}
+
+/// Sample frequencies of an axis with `n` points, spacing `d` and
+/// ordering `spectrum`
+pub fn freq_axis(n: usize, d: f64, spectrum: Spectrum) -> Vec<f64> {
+    match spectrum {
+        Spectrum::Full => fftfreq(n, d),
+        Spectrum::Shifted => {
+            let mut freq = fftfreq(n, d);
+            freq.rotate_right(n / 2);
+            freq
+        }
+        Spectrum::Half => rfftfreq(n, d),
+    }
+}
+
+/// Angular wavenumbers of a periodic axis with `n` points and period `length`
+///
+/// Equal to 2 pi times the frequencies with spacing `length` / n, i.e. the
+/// integer mode numbers for a period of 2 pi.
+pub fn wavenumbers(n: usize, length: f64, spectrum: Spectrum) -> Vec<f64> {
+    freq_axis(n, length / n as f64, spectrum)
+        .into_iter()
+        .map(|f| 2. * PI * f)
+        .collect()
+}
+
+/// Flattened 2D frequency meshes of an m x n array
+///
+/// Returns the frequencies (f0, f1) along axis 0 and 1 at every point of the
+/// spectrum. The shape of the spectrum is `spectrum.0.len(m)` x
+/// `spectrum.1.len(n)`, flattened in `layout`.
+///
+/// # Arguments
+///
+/// * `m` - Number of points along axis 0
+/// * `n` - Number of points along axis 1
+/// * `d` - Sample spacing along both axes
+/// * `spectrum` - Ordering of the frequencies along both axes
+/// * `layout` - Memory order of the spectrum
+pub fn freq_mesh2d(
+    m: usize,
+    n: usize,
+    d: (f64, f64),
+    spectrum: (Spectrum, Spectrum),
+    layout: Layout,
+) -> (Vec<f64>, Vec<f64>) {
+    let f0 = freq_axis(m, d.0, spectrum.0);
+    let f1 = freq_axis(n, d.1, spectrum.1);
+    mesh2d(&f0, &f1, layout)
+}
+
+/// Flattened 2D wavenumber meshes of an m x n array with periods `lengths`
+///
+/// Same as [`freq_mesh2d`], with angular wavenumbers, see [`wavenumbers`].
+pub fn wavenumber_mesh2d(
+    m: usize,
+    n: usize,
+    lengths: (f64, f64),
+    spectrum: (Spectrum, Spectrum),
+    layout: Layout,
+) -> (Vec<f64>, Vec<f64>) {
+    let k0 = wavenumbers(m, lengths.0, spectrum.0);
+    let k1 = wavenumbers(n, lengths.1, spectrum.1);
+    mesh2d(&k0, &k1, layout)
+}
+
+/// Flattened ND frequency meshes of an array of size `shape`
+///
+/// Returns one row-major mesh per axis. Along each axis the number of
+/// frequencies is given by its [`Spectrum`].
+///
+/// # Panics
+///
+/// Panics if `d` or `spectrum` do not have one entry per axis.
+pub fn freq_meshnd(shape: &[usize], d: &[f64], spectrum: &[Spectrum]) -> Vec<Vec<f64>> {
+    assert!(d.len() == shape.len(), "{} != {}", d.len(), shape.len());
+    assert!(
+        spectrum.len() == shape.len(),
+        "{} != {}",
+        spectrum.len(),
+        shape.len()
+    );
+    let axes: Vec<Vec<f64>> = shape
+        .iter()
+        .zip(d.iter().zip(spectrum.iter()))
+        .map(|(&n, (&d, &s))| freq_axis(n, d, s))
+        .collect();
+    meshnd(&axes)
+}
+
+/// Flattened ND wavenumber meshes of an array of size `shape` with periods
+/// `lengths`
+///
+/// Same as [`freq_meshnd`], with angular wavenumbers, see [`wavenumbers`].
+///
+/// # Panics
+///
+/// Panics if `lengths` or `spectrum` do not have one entry per axis.
+pub fn wavenumber_meshnd(shape: &[usize], lengths: &[f64], spectrum: &[Spectrum]) -> Vec<Vec<f64>> {
+    assert!(
+        lengths.len() == shape.len(),
+        "{} != {}",
+        lengths.len(),
+        shape.len()
+    );
+    assert!(
+        spectrum.len() == shape.len(),
+        "{} != {}",
+        spectrum.len(),
+        shape.len()
+    );
+    let axes: Vec<Vec<f64>> = shape
+        .iter()
+        .zip(lengths.iter().zip(spectrum.iter()))
+        .map(|(&n, (&l, &s))| wavenumbers(n, l, s))
+        .collect();
+    meshnd(&axes)
+}
+
+/// Broadcast the 1D vectors `a` (axis 0) and `b` (axis 1) to flattened 2D
+/// meshes in `layout`
+fn mesh2d(a: &[f64], b: &[f64], layout: Layout) -> (Vec<f64>, Vec<f64>) {
+    let size = a.len() * b.len();
+    let mut mesh_a = Vec::with_capacity(size);
+    let mut mesh_b = Vec::with_capacity(size);
+    match layout {
+        Layout::Natural => {
+            for &x in a {
+                mesh_a.extend(std::iter::repeat_n(x, b.len()));
+                mesh_b.extend_from_slice(b);
+            }
+        }
+        Layout::Transposed => {
+            for &y in b {
+                mesh_a.extend_from_slice(a);
+                mesh_b.extend(std::iter::repeat_n(y, a.len()));
+            }
+        }
+    }
+    (mesh_a, mesh_b)
+}
+
+/// Broadcast one 1D vector per axis to flattened row-major ND meshes
+fn meshnd(axes: &[Vec<f64>]) -> Vec<Vec<f64>> {
+    assert!(!axes.is_empty(), "At least one axis is required");
+    let size: usize = axes.iter().map(Vec::len).product();
+    let mut inner = size;
+    axes.iter()
+        .map(|x| {
+            inner /= x.len().max(1);
+            let mut mesh = Vec::with_capacity(size);
+            while mesh.len() < size {
+                for &f in x {
+                    mesh.extend(std::iter::repeat_n(f, inner));
+                }
+            }
+            mesh
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::plan::Fft2dPlan;
+    use crate::shift::fftshift2d;
+    use rustfft::num_complex::Complex;
+    use rustfft::FftDirection;
+
+    fn assert_approx_eq(result: &[f64], expected: &[f64]) {
+        assert!(result.len() == expected.len());
+        for (a, b) in result.iter().zip(expected.iter()) {
+            assert!((a - b).abs() < 1e-12, "{} != {}", a, b);
+        }
+    }
+
+    #[test]
+    fn test_fftfreq() {
+        // numpy.fft.fftfreq / rfftfreq
+        let expected = [0., 1.25, 2.5, 3.75, -5., -3.75, -2.5, -1.25];
+        assert_approx_eq(&fftfreq(8, 0.1), &expected);
+        assert_approx_eq(&fftfreq(5, 1.), &[0., 0.2, 0.4, -0.4, -0.2]);
+        assert_approx_eq(&rfftfreq(8, 0.1), &[0., 1.25, 2.5, 3.75, 5.]);
+        assert_approx_eq(&rfftfreq(5, 1.), &[0., 0.2, 0.4]);
+        let expected = [-0.4, -0.2, 0., 0.2, 0.4];
+        assert_approx_eq(&freq_axis(5, 1., Spectrum::Shifted), &expected);
+        let expected = [0., 1., 2., -2., -1.];
+        assert_approx_eq(&wavenumbers(5, 2. * PI, Spectrum::Full), &expected);
+    }
+
+    /// The peak of the spectrum of a plane wave lies at its frequency
+    #[test]
+    fn test_freq_mesh2d() {
+        let (m, n) = (6, 5);
+        let (a, b) = (-2., 1.);
+        let wave: Vec<Complex<f64>> = (0..m * n)
+            .map(|x| {
+                let (i, j) = ((x / n) as f64, (x % n) as f64);
+                Complex::from_polar(1., 2. * PI * (a * i / m as f64 + b * j / n as f64))
+            })
+            .collect();
+        let cases = [
+            (Spectrum::Full, Layout::Natural),
+            (Spectrum::Full, Layout::Transposed),
+            (Spectrum::Shifted, Layout::Natural),
+        ];
+        for (spectrum, layout) in cases {
+            let mut v = wave.clone();
+            let mut plan = Fft2dPlan::full((m, n), FftDirection::Forward).layout(layout);
+            plan.process(&mut v);
+            if spectrum == Spectrum::Shifted {
+                fftshift2d(&mut v, m, n);
+            }
+            let peak = (0..m * n)
+                .max_by(|&x, &y| v[x].norm().partial_cmp(&v[y].norm()).unwrap())
+                .unwrap();
+            let d = (1. / m as f64, 1. / n as f64);
+            let (f0, f1) = freq_mesh2d(m, n, d, (spectrum, spectrum), layout);
+            assert!((f0[peak] - a).abs() < 1e-12 && (f1[peak] - b).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_freq_meshnd() {
+        let shape = [3, 4, 5];
+        let spectrum = [Spectrum::Full, Spectrum::Shifted, Spectrum::Half];
+        let mesh = freq_meshnd(&shape, &[1., 1., 1.], &spectrum);
+        let f: Vec<Vec<f64>> = (0..3)
+            .map(|i| freq_axis(shape[i], 1., spectrum[i]))
+            .collect();
+        assert!(mesh.iter().all(|x| x.len() == 3 * 4 * 3));
+        for i in 0..3 {
+            for j in 0..4 {
+                for k in 0..3 {
+                    let x = (i * 4 + j) * 3 + k;
+                    assert!(
+                        mesh[0][x] == f[0][i] && mesh[1][x] == f[1][j] && mesh[2][x] == f[2][k]
+                    );
+                }
+            }
+        }
+
+        // 2D mesh in natural layout is the same as the ND mesh
+        let (f0, f1) = freq_mesh2d(
+            4,
+            7,
+            (0.5, 2.),
+            (Spectrum::Full, Spectrum::Half),
+            Layout::Natural,
+        );
+        let mesh = freq_meshnd(&[4, 7], &[0.5, 2.], &[Spectrum::Full, Spectrum::Half]);
+        assert!(f0 == mesh[0] && f1 == mesh[1]);
+    }
+}
@@ -37,13 +37,33 @@
 //!
 //! Advantages of "transpose"-approach
 //! - Performance (~50%)
-//! - No additional dependencies
+//! - No ndarray dependency, only rustfft, realfft (real ffts), rustdct
+//!   (dct/dst) and rayon (parallel versions)
 //!
 //! Disadvantages
-//! - Larger memory requirements
-//! - Not easily parallelizable
+//! - Larger memory requirements (an m x n scratch along the outer axis,
+//!   `fft2d_with_ip_transpose` trades speed for a small work-space)
+//! - Not easily parallelizable (`fft2d_with_transpose_par` splits the
+//!   transposes and the batch of ffts over a rayon thread pool)
 #![allow(dead_code)]
+pub mod batch;
+pub mod convolve;
+pub mod czt;
+pub mod dealias;
+pub mod derivative;
+pub mod distributed;
+pub mod freq;
+pub mod nddct_with_transpose;
 pub mod ndfft_with_ndrustfft;
 pub mod ndfft_with_transpose;
+pub mod nufft;
+pub mod plan;
+pub mod poisson;
+pub mod power_spectrum;
+pub mod shift;
+pub mod strategy;
 pub mod test_array;
 pub mod transpose;
+pub mod view;
+pub mod window;
+pub mod wisdom;
@@ -2,30 +2,28 @@
 
 use ndarray::Array2;
 use ndfft_test::ndfft_with_ndrustfft::fft2d_with_ndrustfft;
-use ndfft_test::ndfft_with_transpose::fft2d_with_transpose;
-use ndfft_test::test_array::test_array2;
+use ndfft_test::plan::Fft2dPlan;
 use ndfft_test::test_array::test_vec;
 use ndrustfft::FftHandler;
 use rustfft::num_complex::Complex;
-use rustfft::FftPlanner;
+use rustfft::FftDirection;
 
 fn main() {
-    let n = 28;
+    let (m, n) = (28, 36);
     let axis = 0;
+    let lane_len = if axis == 0 { m } else { n };
 
     // Ndrustfft
-    let v = test_array2(n);
-    let mut vhat = Array2::<Complex<f64>>::zeros((n, n));
-    let mut handler: FftHandler<f64> = FftHandler::new(n);
+    let v = Array2::from_shape_vec((m, n), test_vec(m * n)).unwrap();
+    let mut vhat = Array2::<Complex<f64>>::zeros((m, n));
+    let mut handler: FftHandler<f64> = FftHandler::new(lane_len);
     fft2d_with_ndrustfft(&v, &mut vhat, &mut handler, axis);
     //println!("{:?}", vhat);
     //println!("");
 
-    let mut v: Vec<Complex<f64>> = test_vec(n * n);
-    let mut scratch = vec![Complex::default(); n * n];
-    let mut planner = FftPlanner::<f64>::new();
-    let fft = planner.plan_fft_forward(n);
-    fft2d_with_transpose(&mut v, &mut scratch, n, n, &fft, axis);
+    let mut v: Vec<Complex<f64>> = test_vec(m * n);
+    let mut plan = Fft2dPlan::new((m, n), axis, FftDirection::Forward);
+    plan.process(&mut v);
     //println!("{:?}", v);
 
     let tol = 1e-6;
@@ -33,5 +31,13 @@ fn main() {
         assert!((a.re - b.re).abs() < tol);
         assert!((a.im - b.im).abs() < tol);
     }
+
+    // Round trip
+    let mut iplan = Fft2dPlan::new((m, n), axis, FftDirection::Inverse);
+    iplan.process(&mut v);
+    for (a, b) in v.iter().zip(test_vec(m * n).iter()) {
+        assert!((a.re - b.re).abs() < tol);
+        assert!((a.im - b.im).abs() < tol);
+    }
     println!("Test successfull");
 }
@@ -0,0 +1,368 @@
+//! Real-to-real transforms (DCT and DST) along an axis of flattened 2D arrays
+//!
+//! Uses the same "transpose" approach as [`crate::ndfft_with_transpose`].
+//! The transforms are unnormalized and follow the convention of scipy and
+//! ndrustfft, e.g. the DCT-II is
+//!
+//! y_k = 2 * sum_n x_n * cos(pi * k * (2n + 1) / (2N))
+use crate::transpose::oop_transpose;
+use rustdct::{Dct1, DctPlanner, Dst1, TransformType2And3};
+use std::sync::Arc;
+
+/// Type of a real-to-real transform
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransformType {
+    /// DCT-I / DST-I
+    Type1,
+    /// DCT-II / DST-II
+    Type2,
+    /// DCT-III / DST-III
+    Type3,
+}
+
+/// Planned Discrete Cosine Transform of a given type and length
+#[derive(Clone)]
+pub enum DctPlan {
+    Dct1(Arc<dyn Dct1<f64>>),
+    Dct2(Arc<dyn TransformType2And3<f64>>),
+    Dct3(Arc<dyn TransformType2And3<f64>>),
+}
+
+/// Planned Discrete Sine Transform of a given type and length
+#[derive(Clone)]
+pub enum DstPlan {
+    Dst1(Arc<dyn Dst1<f64>>),
+    Dst2(Arc<dyn TransformType2And3<f64>>),
+    Dst3(Arc<dyn TransformType2And3<f64>>),
+}
+
+impl DctPlan {
+    /// Plan a DCT of type `kind` and length `n`
+    pub fn new(kind: TransformType, n: usize) -> Self {
+        let mut planner = DctPlanner::<f64>::new();
+        match kind {
+            TransformType::Type1 => Self::Dct1(planner.plan_dct1(n)),
+            TransformType::Type2 => Self::Dct2(planner.plan_dct2(n)),
+            TransformType::Type3 => Self::Dct3(planner.plan_dct3(n)),
+        }
+    }
+
+    /// Length of the transform
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Dct1(p) => p.len(),
+            Self::Dct2(p) | Self::Dct3(p) => p.len(),
+        }
+    }
+
+    /// Required scratch of [`DctPlan::process_lanes`]
+    pub fn get_scratch_len(&self) -> usize {
+        match self {
+            Self::Dct1(p) => p.get_scratch_len(),
+            Self::Dct2(p) | Self::Dct3(p) => p.get_scratch_len(),
+        }
+    }
+
+    /// Transform all contiguous lanes of `buffer`, in-place
+    pub fn process_lanes(&self, buffer: &mut [f64], scratch: &mut [f64]) {
+        for lane in buffer.chunks_exact_mut(self.len()) {
+            match self {
+                Self::Dct1(p) => p.process_dct1_with_scratch(lane, scratch),
+                Self::Dct2(p) => p.process_dct2_with_scratch(lane, scratch),
+                Self::Dct3(p) => p.process_dct3_with_scratch(lane, scratch),
+            }
+            scale_lane(lane);
+        }
+    }
+}
+
+impl DstPlan {
+    /// Plan a DST of type `kind` and length `n`
+    pub fn new(kind: TransformType, n: usize) -> Self {
+        let mut planner = DctPlanner::<f64>::new();
+        match kind {
+            TransformType::Type1 => Self::Dst1(planner.plan_dst1(n)),
+            TransformType::Type2 => Self::Dst2(planner.plan_dst2(n)),
+            TransformType::Type3 => Self::Dst3(planner.plan_dst3(n)),
+        }
+    }
+
+    /// Length of the transform
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Dst1(p) => p.len(),
+            Self::Dst2(p) | Self::Dst3(p) => p.len(),
+        }
+    }
+
+    /// Required scratch of [`DstPlan::process_lanes`]
+    pub fn get_scratch_len(&self) -> usize {
+        match self {
+            Self::Dst1(p) => p.get_scratch_len(),
+            Self::Dst2(p) | Self::Dst3(p) => p.get_scratch_len(),
+        }
+    }
+
+    /// Transform all contiguous lanes of `buffer`, in-place
+    pub fn process_lanes(&self, buffer: &mut [f64], scratch: &mut [f64]) {
+        let scratch_len = self.get_scratch_len();
+        for lane in buffer.chunks_exact_mut(self.len()) {
+            match self {
+                Self::Dst1(p) => {
+                    // rustdct's fft based dst1 expects zero-initialized scratch
+                    scratch[..scratch_len].fill(0.);
+                    p.process_dst1_with_scratch(lane, scratch);
+                }
+                Self::Dst2(p) => p.process_dst2_with_scratch(lane, scratch),
+                Self::Dst3(p) => p.process_dst3_with_scratch(lane, scratch),
+            }
+            scale_lane(lane);
+        }
+    }
+}
+
+/// Scale to the scipy convention, while the lane is still in cache
+fn scale_lane(lane: &mut [f64]) {
+    for x in lane.iter_mut() {
+        *x *= 2.;
+    }
+}
+
+/// Dct along `axis` of a flattened m x n array
+///
+/// See [`crate::ndfft_with_transpose::fft2d_with_transpose`].
+///
+/// # Arguments
+///
+/// * `v` - Flattened 2D array with m * n elements, input and output
+/// * `scratch` - Work-space, at least m * n + `dct.get_scratch_len()` elements
+///   for `axis` = 0 and `dct.get_scratch_len()` elements for `axis` = 1
+/// * `m` - Number of rows
+/// * `n` - Number of cols
+/// * `dct` - Dct plan of length m (`axis` = 0) or n (`axis` = 1)
+/// * `axis` - Axis along which the dct is performed
+///
+/// # Panics
+///
+/// Panics if the length of `dct` does not match the lane length along `axis`,
+/// or if `v` or `scratch` are too small.
+pub fn dct2d_with_transpose(
+    v: &mut [f64],
+    scratch: &mut [f64],
+    m: usize,
+    n: usize,
+    dct: &DctPlan,
+    axis: usize,
+) {
+    let (len, scratch_len) = (dct.len(), dct.get_scratch_len());
+    r2r2d_with_transpose(v, scratch, m, n, len, scratch_len, axis, |x, s| {
+        dct.process_lanes(x, s);
+    });
+}
+
+/// Dst along `axis` of a flattened m x n array
+///
+/// See [`crate::ndfft_with_transpose::fft2d_with_transpose`].
+///
+/// # Arguments
+///
+/// * `v` - Flattened 2D array with m * n elements, input and output
+/// * `scratch` - Work-space, at least m * n + `dst.get_scratch_len()` elements
+///   for `axis` = 0 and `dst.get_scratch_len()` elements for `axis` = 1
+/// * `m` - Number of rows
+/// * `n` - Number of cols
+/// * `dst` - Dst plan of length m (`axis` = 0) or n (`axis` = 1)
+/// * `axis` - Axis along which the dst is performed
+///
+/// # Panics
+///
+/// Panics if the length of `dst` does not match the lane length along `axis`,
+/// or if `v` or `scratch` are too small.
+pub fn dst2d_with_transpose(
+    v: &mut [f64],
+    scratch: &mut [f64],
+    m: usize,
+    n: usize,
+    dst: &DstPlan,
+    axis: usize,
+) {
+    let (len, scratch_len) = (dst.len(), dst.get_scratch_len());
+    r2r2d_with_transpose(v, scratch, m, n, len, scratch_len, axis, |x, s| {
+        dst.process_lanes(x, s);
+    });
+}
+
+/// Transpose, if necessary, process all lanes and transpose back
+#[allow(clippy::too_many_arguments)]
+fn r2r2d_with_transpose<F: Fn(&mut [f64], &mut [f64])>(
+    v: &mut [f64],
+    scratch: &mut [f64],
+    m: usize,
+    n: usize,
+    len: usize,
+    scratch_len: usize,
+    axis: usize,
+    process_lanes: F,
+) {
+    assert!(v.len() == m * n, "{} != {}", v.len(), m * n);
+    assert!(axis < 2, "axis must be 0 or 1, got {}", axis);
+    let lane_len = if axis == 0 { m } else { n };
+    assert!(
+        len == lane_len,
+        "Size mismatch in transform along axis {}, got {} expected {}",
+        axis,
+        len,
+        lane_len
+    );
+    if axis == 1 {
+        assert!(
+            scratch.len() >= scratch_len,
+            "{} < {}",
+            scratch.len(),
+            scratch_len
+        );
+        process_lanes(v, scratch);
+    } else {
+        let required = m * n + scratch_len;
+        assert!(
+            scratch.len() >= required,
+            "{} < {}",
+            scratch.len(),
+            required
+        );
+        let (buffer, extra) = scratch.split_at_mut(m * n);
+        oop_transpose(v, buffer, m, n);
+        process_lanes(buffer, extra);
+        oop_transpose(buffer, v, n, m);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ndfft_with_ndrustfft::dct2d_with_ndrustfft;
+    use ndarray::Array2;
+    use ndrustfft::DctHandler;
+    use std::f64::consts::PI;
+
+    const KINDS: [TransformType; 3] = [
+        TransformType::Type1,
+        TransformType::Type2,
+        TransformType::Type3,
+    ];
+
+    fn test_data(m: usize, n: usize) -> Vec<f64> {
+        (0..m * n).map(|x| ((x * 7) % 11) as f64 - 5.).collect()
+    }
+
+    fn assert_approx_eq(result: &[f64], expected: &[f64]) {
+        let scale = expected.iter().fold(1., |a: f64, b| a.max(b.abs()));
+        assert!(result.len() == expected.len());
+        for (a, b) in result.iter().zip(expected.iter()) {
+            assert!((a - b).abs() < 1e-10 * scale, "{} != {}", a, b);
+        }
+    }
+
+    /// Direct O(n^2) dst of a single lane (scipy convention)
+    fn dst_direct(x: &[f64], kind: TransformType) -> Vec<f64> {
+        let n = x.len();
+        let nf = n as f64;
+        (0..n)
+            .map(|k| {
+                let kf = k as f64;
+                let sum: f64 = x
+                    .iter()
+                    .enumerate()
+                    .map(|(j, xj)| {
+                        let jf = j as f64;
+                        match kind {
+                            TransformType::Type1 => {
+                                xj * (PI * (kf + 1.) * (jf + 1.) / (nf + 1.)).sin()
+                            }
+                            TransformType::Type2 => {
+                                xj * (PI * (kf + 1.) * (2. * jf + 1.) / (2. * nf)).sin()
+                            }
+                            TransformType::Type3 if j == n - 1 => {
+                                xj * (PI * (2. * kf + 1.) / 2.).sin() / 2.
+                            }
+                            TransformType::Type3 => {
+                                xj * (PI * (2. * kf + 1.) * (jf + 1.) / (2. * nf)).sin()
+                            }
+                        }
+                    })
+                    .sum();
+                2. * sum
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_dct2d() {
+        // (509, 3) needs more scratch than m * n along axis 0
+        let shapes = [(16, 16), (30, 17), (9, 64), (509, 3)];
+        for (m, n) in shapes {
+            for axis in [0, 1] {
+                let lane_len = if axis == 0 { m } else { n };
+                for kind in KINDS {
+                    // Ndrustfft
+                    let v = Array2::from_shape_vec((m, n), test_data(m, n)).unwrap();
+                    let mut vhat = Array2::<f64>::zeros((m, n));
+                    let mut handler: DctHandler<f64> = DctHandler::new(lane_len);
+                    dct2d_with_ndrustfft(&v, &mut vhat, &mut handler, axis, kind);
+
+                    // Transpose
+                    let dct = DctPlan::new(kind, lane_len);
+                    let mut v = test_data(m, n);
+                    let mut scratch = vec![0.; m * n + dct.get_scratch_len()];
+                    dct2d_with_transpose(&mut v, &mut scratch, m, n, &dct, axis);
+                    assert_approx_eq(&v, vhat.as_slice().unwrap());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_dst2d() {
+        // (509, 3) needs more scratch than m * n along axis 0
+        let shapes = [(16, 16), (30, 17), (9, 64), (509, 3)];
+        for (m, n) in shapes {
+            for axis in [0, 1] {
+                let lane_len = if axis == 0 { m } else { n };
+                for kind in KINDS {
+                    // Direct sum along lanes
+                    let v = Array2::from_shape_vec((m, n), test_data(m, n)).unwrap();
+                    let mut vhat = Array2::<f64>::zeros((m, n));
+                    for (x, mut y) in v
+                        .lanes(ndarray::Axis(axis))
+                        .into_iter()
+                        .zip(vhat.lanes_mut(ndarray::Axis(axis)))
+                    {
+                        let x = x.to_vec();
+                        for (a, b) in y.iter_mut().zip(dst_direct(&x, kind)) {
+                            *a = b;
+                        }
+                    }
+
+                    // Transpose
+                    let dst = DstPlan::new(kind, lane_len);
+                    let mut v = test_data(m, n);
+                    let mut scratch = vec![0.; m * n + dst.get_scratch_len()];
+                    dst2d_with_transpose(&mut v, &mut scratch, m, n, &dst, axis);
+                    assert_approx_eq(&v, vhat.as_slice().unwrap());
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "1527 < ")]
+    fn test_dct2d_short_scratch() {
+        let (m, n) = (509, 3);
+        let dct = DctPlan::new(TransformType::Type2, m);
+        let mut v = test_data(m, n);
+        let mut scratch = vec![0.; m * n];
+        dct2d_with_transpose(&mut v, &mut scratch, m, n, &dct, 0);
+    }
+}
@@ -1,5 +1,9 @@
+use crate::nddct_with_transpose::TransformType;
 use ndarray::Array2;
-use ndrustfft::{ndfft, Complex, FftHandler};
+use ndrustfft::{
+    nddct1, nddct2, nddct3, ndfft, ndfft_r2c, ndifft_r2c, Complex, DctHandler, FftHandler,
+    R2cFftHandler,
+};
 
 pub fn fft2d_with_ndrustfft(
     v: &Array2<Complex<f64>>,
@@ -9,3 +13,35 @@ pub fn fft2d_with_ndrustfft(
 ) {
     ndfft(v, vhat, handler, axis);
 }
+
+pub fn fft2d_r2c_with_ndrustfft(
+    v: &Array2<f64>,
+    vhat: &mut Array2<Complex<f64>>,
+    handler: &mut R2cFftHandler<f64>,
+    axis: usize,
+) {
+    ndfft_r2c(v, vhat, handler, axis);
+}
+
+pub fn ifft2d_r2c_with_ndrustfft(
+    vhat: &Array2<Complex<f64>>,
+    v: &mut Array2<f64>,
+    handler: &mut R2cFftHandler<f64>,
+    axis: usize,
+) {
+    ndifft_r2c(vhat, v, handler, axis);
+}
+
+pub fn dct2d_with_ndrustfft(
+    v: &Array2<f64>,
+    vhat: &mut Array2<f64>,
+    handler: &mut DctHandler<f64>,
+    axis: usize,
+    kind: TransformType,
+) {
+    match kind {
+        TransformType::Type1 => nddct1(v, vhat, handler, axis),
+        TransformType::Type2 => nddct2(v, vhat, handler, axis),
+        TransformType::Type3 => nddct3(v, vhat, handler, axis),
+    }
+}
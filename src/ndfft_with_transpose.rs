@@ -1,25 +1,1520 @@
-use crate::transpose::oop_transpose;
-use rustfft::{num_complex::Complex, Fft};
+use crate::transpose::{
+    ip_transpose, oop_transpose, oop_transpose_map, oop_transpose_par, oop_transpose_par_map,
+};
+use rayon::prelude::*;
+use realfft::{ComplexToReal, RealToComplex};
+use rustfft::{num_complex::Complex, num_traits::Zero, Fft, FftDirection, FftNum};
 use std::sync::Arc;
 
-pub fn fft2d_with_transpose(
-    v: &mut [Complex<f64>],
-    scratch: &mut [Complex<f64>],
+/// Number of elements transformed and scaled at once along the contiguous axis
+pub(crate) const CHUNK_SIZE: usize = 4096;
+
+/// Normalization of forward and inverse transforms
+///
+/// Follows the `norm` keyword of numpy.fft:
+///
+/// | Normalization | forward     | inverse     |
+/// | :---          | :---:       | :---:       |
+/// | `Backward`    | 1           | 1 / n       |
+/// | `Ortho`       | 1 / sqrt(n) | 1 / sqrt(n) |
+/// | `Forward`     | 1 / n       | 1           |
+///
+/// where n is the total number of transformed points.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Normalization {
+    /// No scaling of the forward, 1 / n scaling of the inverse transform
+    #[default]
+    Backward,
+    /// 1 / sqrt(n) scaling of both transforms
+    Ortho,
+    /// 1 / n scaling of the forward, no scaling of the inverse transform
+    Forward,
+}
+
+impl Normalization {
+    /// Scaling factor of a transform of `n` points in `direction`
+    pub fn factor(self, n: usize, direction: FftDirection) -> f64 {
+        match (self, direction) {
+            (Self::Backward, FftDirection::Forward) | (Self::Forward, FftDirection::Inverse) => 1.,
+            (Self::Backward, FftDirection::Inverse) | (Self::Forward, FftDirection::Forward) => {
+                1. / n as f64
+            }
+            (Self::Ortho, _) => 1. / (n as f64).sqrt(),
+        }
+    }
+}
+
+/// Memory order of the data of a flattened m x n array
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Layout {
+    /// Row-major m x n array
+    #[default]
+    Natural,
+    /// Transposed, i.e. row-major n x m array
+    Transposed,
+}
+
+/// Fft along `axis` of a flattened m x n array
+///
+/// The data along `axis` is made contiguous by a transpose (only for
+/// `axis` = 0), all lanes are transformed with a single call to `fft`
+/// and the data is transposed back.
+///
+/// The transform is performed in the direction of `fft` with
+/// [`Normalization::Backward`], i.e. an inverse plan is scaled by 1 / len.
+/// See [`fft2d_with_transpose_norm`] and [`ifft2d_with_transpose`] for other
+/// normalizations.
+///
+/// # Arguments
+///
+/// * `v` - Flattened 2D array with m * n elements, input and output
+/// * `scratch` - Work-space, at least m * n + `fft.get_inplace_scratch_len()`
+///   elements for `axis` = 0 and `fft.get_inplace_scratch_len()` elements
+///   for `axis` = 1
+/// * `m` - Number of rows
+/// * `n` - Number of cols
+/// * `fft` - Fft plan of length m (`axis` = 0) or n (`axis` = 1)
+/// * `axis` - Axis along which the fft is performed
+///
+/// # Panics
+///
+/// Panics if the length of `fft` does not match the lane length along `axis`,
+/// or if `v` or `scratch` are too small.
+///
+/// # Breaking change
+///
+/// Earlier versions required only m * n elements of scratch for `axis` = 0
+/// and used `v` as scratch of the fft, which fails for plans that need more
+/// scratch than m * n elements, e.g. Bluestein plans of prime lengths.
+/// Callers which size the scratch to m * n must add
+/// `fft.get_inplace_scratch_len()`.
+pub fn fft2d_with_transpose<T: FftNum>(
+    v: &mut [Complex<T>],
+    scratch: &mut [Complex<T>],
     m: usize,
     n: usize,
-    fft: &Arc<dyn Fft<f64>>,
+    fft: &Arc<dyn Fft<T>>,
     axis: usize,
 ) {
-    assert!(v.len() == m * n);
-    if axis == 1 {
+    fft2d_axis(v, scratch, m, n, fft, axis, Normalization::Backward);
+}
+
+/// Forward fft along `axis` of a flattened m x n array with normalization
+/// `norm`
+///
+/// Same as [`fft2d_with_transpose`], but the plan must be a forward plan.
+/// For `axis` = 0 the scaling of `norm` is applied during the transpose back,
+/// for `axis` = 1 the lanes are transformed in blocks, each of which is scaled
+/// right after its fft while it is still in cache.
+///
+/// # Arguments
+///
+/// * `v` - Flattened 2D array with m * n elements, input and output
+/// * `scratch` - Work-space, at least m * n + `fft.get_inplace_scratch_len()`
+///   elements for `axis` = 0 and `fft.get_inplace_scratch_len()` elements
+///   for `axis` = 1
+/// * `m` - Number of rows
+/// * `n` - Number of cols
+/// * `fft` - Forward fft plan of length m (`axis` = 0) or n (`axis` = 1)
+/// * `axis` - Axis along which the fft is performed
+/// * `norm` - Normalization of the transform
+///
+/// # Panics
+///
+/// Panics if the length of `fft` does not match the lane length along `axis`,
+/// if `fft` is not a forward plan, or if `v` or `scratch` are too small.
+pub fn fft2d_with_transpose_norm<T: FftNum>(
+    v: &mut [Complex<T>],
+    scratch: &mut [Complex<T>],
+    m: usize,
+    n: usize,
+    fft: &Arc<dyn Fft<T>>,
+    axis: usize,
+    norm: Normalization,
+) {
+    assert_direction(fft, FftDirection::Forward);
+    fft2d_axis(v, scratch, m, n, fft, axis, norm);
+}
+
+/// Inverse fft along `axis` of a flattened m x n array
+///
+/// Counterpart of [`fft2d_with_transpose_norm`].
+///
+/// # Arguments
+///
+/// * `v` - Flattened 2D array with m * n elements, input and output
+/// * `scratch` - Work-space, at least m * n + `ifft.get_inplace_scratch_len()`
+///   elements for `axis` = 0 and `ifft.get_inplace_scratch_len()` elements
+///   for `axis` = 1
+/// * `m` - Number of rows
+/// * `n` - Number of cols
+/// * `ifft` - Inverse fft plan of length m (`axis` = 0) or n (`axis` = 1)
+/// * `axis` - Axis along which the fft is performed
+/// * `norm` - Normalization of the transform
+///
+/// # Panics
+///
+/// Panics if the length of `ifft` does not match the lane length along `axis`,
+/// if `ifft` is not an inverse plan, or if `v` or `scratch` are too small.
+pub fn ifft2d_with_transpose<T: FftNum>(
+    v: &mut [Complex<T>],
+    scratch: &mut [Complex<T>],
+    m: usize,
+    n: usize,
+    ifft: &Arc<dyn Fft<T>>,
+    axis: usize,
+    norm: Normalization,
+) {
+    assert_direction(ifft, FftDirection::Inverse);
+    fft2d_axis(v, scratch, m, n, ifft, axis, norm);
+}
+
+/// Fft along a single axis in the direction of `fft`
+fn fft2d_axis<T: FftNum>(
+    v: &mut [Complex<T>],
+    scratch: &mut [Complex<T>],
+    m: usize,
+    n: usize,
+    fft: &Arc<dyn Fft<T>>,
+    axis: usize,
+    norm: Normalization,
+) {
+    assert!(axis < 2, "axis must be 0 or 1, got {}", axis);
+    fftnd_axis(v, scratch, &[m, n], fft, axis, norm);
+}
+
+/// Forward fft along `axis` of a flattened N-dimensional array
+///
+/// The array is stored in row-major (C) order with dimensions `shape`.
+/// It is viewed as outer x len x inner, where len is the size along `axis`
+/// and outer (inner) is the product of all dimensions before (after) `axis`.
+/// Each of the outer blocks of size len x inner is transposed, which moves
+/// `axis` innermost while leaving all other axes in place. All lanes are
+/// then transformed with a single call to `fft`, and the blocks are
+/// transposed back. No data is moved if `axis` is the last axis.
+///
+/// # Arguments
+///
+/// * `v` - Flattened ND array with `shape.iter().product()` elements, input and output
+/// * `scratch` - Work-space, at least `v.len()` + `fft.get_inplace_scratch_len()`
+///   elements if `axis` is not the last axis, `fft.get_inplace_scratch_len()`
+///   elements otherwise
+/// * `shape` - Dimensions of the array
+/// * `fft` - Forward fft plan of length `shape[axis]`
+/// * `axis` - Axis along which the fft is performed
+/// * `norm` - Normalization of the transform
+///
+/// # Panics
+///
+/// Panics if the length of `fft` does not match `shape[axis]`,
+/// if `fft` is not a forward plan, or if `v` or `scratch` are too small.
+pub fn fftnd_with_transpose<T: FftNum>(
+    v: &mut [Complex<T>],
+    scratch: &mut [Complex<T>],
+    shape: &[usize],
+    fft: &Arc<dyn Fft<T>>,
+    axis: usize,
+    norm: Normalization,
+) {
+    assert_direction(fft, FftDirection::Forward);
+    fftnd_axis(v, scratch, shape, fft, axis, norm);
+}
+
+/// Inverse fft along `axis` of a flattened N-dimensional array
+///
+/// Counterpart of [`fftnd_with_transpose`].
+///
+/// # Arguments
+///
+/// * `v` - Flattened ND array with `shape.iter().product()` elements, input and output
+/// * `scratch` - Work-space, at least `v.len()` + `ifft.get_inplace_scratch_len()`
+///   elements if `axis` is not the last axis, `ifft.get_inplace_scratch_len()`
+///   elements otherwise
+/// * `shape` - Dimensions of the array
+/// * `ifft` - Inverse fft plan of length `shape[axis]`
+/// * `axis` - Axis along which the fft is performed
+/// * `norm` - Normalization of the transform
+///
+/// # Panics
+///
+/// Panics if the length of `ifft` does not match `shape[axis]`,
+/// if `ifft` is not an inverse plan, or if `v` or `scratch` are too small.
+pub fn ifftnd_with_transpose<T: FftNum>(
+    v: &mut [Complex<T>],
+    scratch: &mut [Complex<T>],
+    shape: &[usize],
+    ifft: &Arc<dyn Fft<T>>,
+    axis: usize,
+    norm: Normalization,
+) {
+    assert_direction(ifft, FftDirection::Inverse);
+    fftnd_axis(v, scratch, shape, ifft, axis, norm);
+}
+
+/// Fft along a single axis of an ND array in the direction of `fft`
+fn fftnd_axis<T: FftNum>(
+    v: &mut [Complex<T>],
+    scratch: &mut [Complex<T>],
+    shape: &[usize],
+    fft: &Arc<dyn Fft<T>>,
+    axis: usize,
+    norm: Normalization,
+) {
+    assert!(
+        axis < shape.len(),
+        "axis {} out of bounds for {} dimensions",
+        axis,
+        shape.len()
+    );
+    let scale = norm.factor(shape[axis], fft.fft_direction());
+    fftnd_axis_scaled(v, scratch, shape, fft, axis, scale);
+}
+
+/// Fft along a single axis of an ND array, the output is multiplied by `scale`
+pub(crate) fn fftnd_axis_scaled<T: FftNum>(
+    v: &mut [Complex<T>],
+    scratch: &mut [Complex<T>],
+    shape: &[usize],
+    fft: &Arc<dyn Fft<T>>,
+    axis: usize,
+    scale: f64,
+) {
+    let size: usize = shape.iter().product();
+    assert!(v.len() == size, "{} != {}", v.len(), size);
+    let len = shape[axis];
+    let inner: usize = shape[axis + 1..].iter().product();
+    assert_length(fft, len, axis);
+    if size == 0 {
+        return;
+    }
+    if inner == 1 {
         let scratch_len = fft.get_inplace_scratch_len();
         assert!(scratch.len() >= scratch_len);
+        process_lanes_scaled(v, &mut scratch[..scratch_len], fft, scale);
+    } else {
+        let extra_len = fft.get_inplace_scratch_len();
+        let scratch_len = size + extra_len;
+        assert!(
+            scratch.len() >= scratch_len,
+            "{} < {}",
+            scratch.len(),
+            scratch_len
+        );
+        let (buffer, extra) = scratch.split_at_mut(size);
+        let block = len * inner;
+        for (src, dst) in v.chunks_exact(block).zip(buffer.chunks_exact_mut(block)) {
+            oop_transpose(src, dst, len, inner);
+        }
+        fft.process_with_scratch(buffer, &mut extra[..extra_len]);
+        for (src, dst) in buffer.chunks_exact(block).zip(v.chunks_exact_mut(block)) {
+            transpose_scaled(src, dst, inner, len, scale);
+        }
+    }
+}
+
+/// Fft of all contiguous lanes of `v`, scaled by `scale`
+///
+/// Unless `scale` is 1, the lanes are processed in blocks of about
+/// [`CHUNK_SIZE`] elements, which are scaled while still in cache.
+pub(crate) fn process_lanes_scaled<T: FftNum>(
+    v: &mut [Complex<T>],
+    scratch: &mut [Complex<T>],
+    fft: &Arc<dyn Fft<T>>,
+    scale: f64,
+) {
+    #[allow(clippy::float_cmp)]
+    if scale == 1. {
         fft.process_with_scratch(v, scratch);
+        return;
+    }
+    let len = fft.len().max(1);
+    let lanes_per_chunk = (CHUNK_SIZE / len).max(1);
+    for chunk in v.chunks_mut(lanes_per_chunk * len) {
+        fft.process_with_scratch(chunk, scratch);
+        scale_inplace(chunk, scale);
+    }
+}
+
+/// Forward fft along both axes of a flattened m x n array
+///
+/// The fft along axis 1 is performed first, while the data is still
+/// contiguous. The data is then transposed once, the fft along axis 0 is
+/// performed on the transposed data and a second transpose restores the
+/// original order. This requires two transposes in total, the same
+/// number as a single fft along axis 0. The scaling of `norm` is
+/// applied during the second transpose.
+///
+/// # Arguments
+///
+/// * `v` - Flattened 2D array with m * n elements, input and output
+/// * `scratch` - Work-space of at least m * n + `fft_m.get_inplace_scratch_len()`
+///   and `fft_n.get_inplace_scratch_len()` elements
+/// * `m` - Number of rows
+/// * `n` - Number of cols
+/// * `fft_m` - Forward fft plan of length m
+/// * `fft_n` - Forward fft plan of length n
+/// * `norm` - Normalization of the transform
+///
+/// # Panics
+///
+/// Panics if the plans do not match the shape, are not forward plans,
+/// or if `v` or `scratch` are too small.
+pub fn fft2d_full<T: FftNum>(
+    v: &mut [Complex<T>],
+    scratch: &mut [Complex<T>],
+    m: usize,
+    n: usize,
+    fft_m: &Arc<dyn Fft<T>>,
+    fft_n: &Arc<dyn Fft<T>>,
+    norm: Normalization,
+) {
+    assert_direction(fft_m, FftDirection::Forward);
+    assert_direction(fft_n, FftDirection::Forward);
+    fft2d_both_axes(v, scratch, m, n, fft_m, fft_n, norm);
+}
+
+/// Inverse fft along both axes of a flattened m x n array
+///
+/// Counterpart of [`fft2d_full`].
+///
+/// # Arguments
+///
+/// * `v` - Flattened 2D array with m * n elements, input and output
+/// * `scratch` - Work-space of at least m * n + `ifft_m.get_inplace_scratch_len()`
+///   and `ifft_n.get_inplace_scratch_len()` elements
+/// * `m` - Number of rows
+/// * `n` - Number of cols
+/// * `ifft_m` - Inverse fft plan of length m
+/// * `ifft_n` - Inverse fft plan of length n
+/// * `norm` - Normalization of the transform
+///
+/// # Panics
+///
+/// Panics if the plans do not match the shape, are not inverse plans,
+/// or if `v` or `scratch` are too small.
+pub fn ifft2d_full<T: FftNum>(
+    v: &mut [Complex<T>],
+    scratch: &mut [Complex<T>],
+    m: usize,
+    n: usize,
+    ifft_m: &Arc<dyn Fft<T>>,
+    ifft_n: &Arc<dyn Fft<T>>,
+    norm: Normalization,
+) {
+    assert_direction(ifft_m, FftDirection::Inverse);
+    assert_direction(ifft_n, FftDirection::Inverse);
+    fft2d_both_axes(v, scratch, m, n, ifft_m, ifft_n, norm);
+}
+
+/// Fft along axis 1, transpose, fft along axis 0, transpose back
+fn fft2d_both_axes<T: FftNum>(
+    v: &mut [Complex<T>],
+    scratch: &mut [Complex<T>],
+    m: usize,
+    n: usize,
+    fft_m: &Arc<dyn Fft<T>>,
+    fft_n: &Arc<dyn Fft<T>>,
+    norm: Normalization,
+) {
+    assert!(v.len() == m * n, "{} != {}", v.len(), m * n);
+    assert_length(fft_m, m, 0);
+    assert_length(fft_n, n, 1);
+    let m_inplace = fft_m.get_inplace_scratch_len();
+    let n_inplace = fft_n.get_inplace_scratch_len();
+    assert!(scratch.len() >= (m * n + m_inplace).max(n_inplace));
+    let scale = norm.factor(m * n, fft_m.fft_direction());
+    fft_n.process_with_scratch(v, &mut scratch[..n_inplace]);
+    let (buffer, extra) = scratch.split_at_mut(m * n);
+    oop_transpose(v, buffer, m, n);
+    fft_m.process_with_scratch(buffer, &mut extra[..m_inplace]);
+    transpose_scaled(buffer, v, n, m, scale);
+}
+
+/// Forward fft along `axis` of a flattened m x n array, with selectable output layout
+///
+/// Same as [`fft2d_with_transpose_norm`], but for `axis` = 0 and `layout` =
+/// [`Layout::Transposed`] the transpose back is skipped and the spectrum is
+/// returned as n x m array. The matching inverse [`ifft2d_with_layout`]
+/// accepts the transposed spectrum and restores the natural order, so that
+/// a forward-inverse round trip requires only two instead of four transposes.
+/// The fft is performed out-of-place from `scratch` into `v`, and the scaling of
+/// `norm` is applied during the transpose.
+///
+/// A transposed layout saves nothing along `axis` = 1, in this case
+/// the output is always in natural order.
+///
+/// # Arguments
+///
+/// * `v` - Flattened 2D array with m * n elements, input and output
+/// * `scratch` - Work-space, at least m * n + `fft.get_outofplace_scratch_len()`
+///   elements for a transposed output, see [`fft2d_with_transpose`] otherwise
+/// * `m` - Number of rows
+/// * `n` - Number of cols
+/// * `fft` - Forward fft plan of length m (`axis` = 0) or n (`axis` = 1)
+/// * `axis` - Axis along which the fft is performed
+/// * `norm` - Normalization of the transform
+/// * `layout` - Requested layout of the output
+///
+/// # Returns
+///
+/// Layout of the output
+///
+/// # Panics
+///
+/// Panics if the length of `fft` does not match the lane length along `axis`,
+/// if `fft` is not a forward plan, or if `v` or `scratch` are too small.
+#[allow(clippy::too_many_arguments)]
+pub fn fft2d_with_layout<T: FftNum>(
+    v: &mut [Complex<T>],
+    scratch: &mut [Complex<T>],
+    m: usize,
+    n: usize,
+    fft: &Arc<dyn Fft<T>>,
+    axis: usize,
+    norm: Normalization,
+    layout: Layout,
+) -> Layout {
+    assert_direction(fft, FftDirection::Forward);
+    if axis == 0 && layout == Layout::Transposed {
+        assert!(v.len() == m * n, "{} != {}", v.len(), m * n);
+        assert_length(fft, m, 0);
+        let scale = norm.factor(m, FftDirection::Forward);
+        transpose_then_fft(v, scratch, m, n, fft, scale);
+        Layout::Transposed
     } else {
-        let scratch_len = m * n;
-        assert!(scratch.len() >= scratch_len);
-        oop_transpose(&v, scratch, m, n);
-        fft.process_with_scratch(scratch, v);
-        oop_transpose(&scratch, v, n, m);
+        fft2d_axis(v, scratch, m, n, fft, axis, norm);
+        Layout::Natural
+    }
+}
+
+/// Inverse fft along `axis` of a flattened m x n array, with selectable input layout
+///
+/// Counterpart of [`fft2d_with_layout`]. The output is always in natural order.
+///
+/// # Arguments
+///
+/// * `v` - Flattened 2D array with m * n elements, input and output
+/// * `scratch` - Work-space, at least m * n + `ifft.get_outofplace_scratch_len()`
+///   elements for a transposed input, see [`ifft2d_with_transpose`] otherwise
+/// * `m` - Number of rows
+/// * `n` - Number of cols
+/// * `ifft` - Inverse fft plan of length m (`axis` = 0) or n (`axis` = 1)
+/// * `axis` - Axis along which the fft is performed
+/// * `norm` - Normalization of the transform
+/// * `layout` - Layout of the input
+///
+/// # Panics
+///
+/// Panics if the length of `ifft` does not match the lane length along `axis`,
+/// if `ifft` is not an inverse plan, if `layout` is transposed for `axis` = 1,
+/// or if `v` or `scratch` are too small.
+#[allow(clippy::too_many_arguments)]
+pub fn ifft2d_with_layout<T: FftNum>(
+    v: &mut [Complex<T>],
+    scratch: &mut [Complex<T>],
+    m: usize,
+    n: usize,
+    ifft: &Arc<dyn Fft<T>>,
+    axis: usize,
+    norm: Normalization,
+    layout: Layout,
+) {
+    assert_direction(ifft, FftDirection::Inverse);
+    if layout == Layout::Transposed {
+        assert!(
+            axis == 0,
+            "transposed layout is only supported along axis 0"
+        );
+        assert!(v.len() == m * n, "{} != {}", v.len(), m * n);
+        assert_length(ifft, m, 0);
+        let scale = norm.factor(m, FftDirection::Inverse);
+        fft_then_transpose(v, scratch, n, m, ifft, scale);
+    } else {
+        fft2d_axis(v, scratch, m, n, ifft, axis, norm);
+    }
+}
+
+/// Forward fft along both axes of a flattened m x n array, with selectable output layout
+///
+/// Same as [`fft2d_full`], but for `layout` = [`Layout::Transposed`] the
+/// transpose back is skipped and the spectrum is returned as n x m array.
+///
+/// # Arguments
+///
+/// * `v` - Flattened 2D array with m * n elements, input and output
+/// * `scratch` - Work-space, at least m * n + `fft_m.get_outofplace_scratch_len()`
+///   and `fft_n.get_inplace_scratch_len()` elements for a transposed output,
+///   see [`fft2d_full`] otherwise
+/// * `m` - Number of rows
+/// * `n` - Number of cols
+/// * `fft_m` - Forward fft plan of length m
+/// * `fft_n` - Forward fft plan of length n
+/// * `norm` - Normalization of the transform
+/// * `layout` - Requested layout of the output
+///
+/// # Returns
+///
+/// Layout of the output
+///
+/// # Panics
+///
+/// Panics if the plans do not match the shape, are not forward plans,
+/// or if `v` or `scratch` are too small.
+#[allow(clippy::too_many_arguments)]
+pub fn fft2d_full_with_layout<T: FftNum>(
+    v: &mut [Complex<T>],
+    scratch: &mut [Complex<T>],
+    m: usize,
+    n: usize,
+    fft_m: &Arc<dyn Fft<T>>,
+    fft_n: &Arc<dyn Fft<T>>,
+    norm: Normalization,
+    layout: Layout,
+) -> Layout {
+    assert_direction(fft_m, FftDirection::Forward);
+    assert_direction(fft_n, FftDirection::Forward);
+    if layout == Layout::Transposed {
+        assert!(v.len() == m * n, "{} != {}", v.len(), m * n);
+        assert_length(fft_m, m, 0);
+        assert_length(fft_n, n, 1);
+        assert!(scratch.len() >= fft_n.get_inplace_scratch_len());
+        let scale = norm.factor(m * n, FftDirection::Forward);
+        fft_n.process_with_scratch(v, scratch);
+        transpose_then_fft(v, scratch, m, n, fft_m, scale);
+    } else {
+        fft2d_both_axes(v, scratch, m, n, fft_m, fft_n, norm);
+    }
+    layout
+}
+
+/// Inverse fft along both axes of a flattened m x n array, with selectable input layout
+///
+/// Counterpart of [`fft2d_full_with_layout`]. The output is always in natural order.
+///
+/// # Arguments
+///
+/// * `v` - Flattened 2D array with m * n elements, input and output
+/// * `scratch` - Work-space, at least m * n + `ifft_m.get_outofplace_scratch_len()`
+///   and `ifft_n.get_inplace_scratch_len()` elements for a transposed input,
+///   see [`ifft2d_full`] otherwise
+/// * `m` - Number of rows
+/// * `n` - Number of cols
+/// * `ifft_m` - Inverse fft plan of length m
+/// * `ifft_n` - Inverse fft plan of length n
+/// * `norm` - Normalization of the transform
+/// * `layout` - Layout of the input
+///
+/// # Panics
+///
+/// Panics if the plans do not match the shape, are not inverse plans,
+/// or if `v` or `scratch` are too small.
+#[allow(clippy::too_many_arguments)]
+pub fn ifft2d_full_with_layout<T: FftNum>(
+    v: &mut [Complex<T>],
+    scratch: &mut [Complex<T>],
+    m: usize,
+    n: usize,
+    ifft_m: &Arc<dyn Fft<T>>,
+    ifft_n: &Arc<dyn Fft<T>>,
+    norm: Normalization,
+    layout: Layout,
+) {
+    assert_direction(ifft_m, FftDirection::Inverse);
+    assert_direction(ifft_n, FftDirection::Inverse);
+    if layout == Layout::Transposed {
+        assert!(v.len() == m * n, "{} != {}", v.len(), m * n);
+        assert_length(ifft_m, m, 0);
+        assert_length(ifft_n, n, 1);
+        assert!(scratch.len() >= ifft_n.get_inplace_scratch_len());
+        let scale = norm.factor(m * n, FftDirection::Inverse);
+        fft_then_transpose(v, scratch, n, m, ifft_m, scale);
+        ifft_n.process_with_scratch(v, scratch);
+    } else {
+        fft2d_both_axes(v, scratch, m, n, ifft_m, ifft_n, norm);
+    }
+}
+
+/// Transpose rows x cols array `v` into `scratch`, then fft along the
+/// contiguous lanes of length rows out-of-place back into `v`
+///
+/// The output is scaled by `scale` during the transpose.
+fn transpose_then_fft<T: FftNum>(
+    v: &mut [Complex<T>],
+    scratch: &mut [Complex<T>],
+    rows: usize,
+    cols: usize,
+    fft: &Arc<dyn Fft<T>>,
+    scale: f64,
+) {
+    let size = rows * cols;
+    let extra_len = fft.get_outofplace_scratch_len();
+    assert!(scratch.len() >= size + extra_len);
+    let (buffer, extra) = scratch.split_at_mut(size);
+    transpose_scaled(v, buffer, rows, cols, scale);
+    fft.process_outofplace_with_scratch(buffer, v, &mut extra[..extra_len]);
+}
+
+/// Fft along the contiguous lanes of length cols of the rows x cols array `v`
+/// out-of-place into `scratch`, then transpose back into `v`
+///
+/// The output is scaled by `scale` during the transpose.
+fn fft_then_transpose<T: FftNum>(
+    v: &mut [Complex<T>],
+    scratch: &mut [Complex<T>],
+    rows: usize,
+    cols: usize,
+    fft: &Arc<dyn Fft<T>>,
+    scale: f64,
+) {
+    let size = rows * cols;
+    let extra_len = fft.get_outofplace_scratch_len();
+    assert!(scratch.len() >= size + extra_len);
+    let (buffer, extra) = scratch.split_at_mut(size);
+    fft.process_outofplace_with_scratch(v, buffer, &mut extra[..extra_len]);
+    transpose_scaled(buffer, v, rows, cols, scale);
+}
+
+/// Low-memory fft along `axis` of a flattened m x n array
+///
+/// Same as [`fft2d_with_transpose_norm`], but for `axis` = 0 the data is
+/// transposed in-place with [`ip_transpose`], so no m * n scratch is needed.
+/// The workspace `work` is used for the in-place transposes and as fft
+/// scratch. Larger workspaces make the transposes faster, see
+/// [`ip_workspace_len`] for the minimum size.
+///
+/// # Arguments
+///
+/// * `v` - Flattened 2D array with m * n elements, input and output
+/// * `work` - Work-space, at least [`ip_workspace_len`] elements
+/// * `m` - Number of rows
+/// * `n` - Number of cols
+/// * `fft` - Forward fft plan of length m (`axis` = 0) or n (`axis` = 1)
+/// * `axis` - Axis along which the fft is performed
+/// * `norm` - Normalization of the transform
+///
+/// # Panics
+///
+/// Panics if the length of `fft` does not match the lane length along `axis`,
+/// if `fft` is not a forward plan, or if `v` or `work` are too small.
+pub fn fft2d_with_ip_transpose<T: FftNum>(
+    v: &mut [Complex<T>],
+    work: &mut [Complex<T>],
+    m: usize,
+    n: usize,
+    fft: &Arc<dyn Fft<T>>,
+    axis: usize,
+    norm: Normalization,
+) {
+    assert_direction(fft, FftDirection::Forward);
+    fft2d_axis_ip(v, work, m, n, fft, axis, norm);
+}
+
+/// Low-memory inverse fft along `axis` of a flattened m x n array
+///
+/// Counterpart of [`fft2d_with_ip_transpose`].
+///
+/// # Arguments
+///
+/// * `v` - Flattened 2D array with m * n elements, input and output
+/// * `work` - Work-space, at least [`ip_workspace_len`] elements
+/// * `m` - Number of rows
+/// * `n` - Number of cols
+/// * `ifft` - Inverse fft plan of length m (`axis` = 0) or n (`axis` = 1)
+/// * `axis` - Axis along which the fft is performed
+/// * `norm` - Normalization of the transform
+///
+/// # Panics
+///
+/// Panics if the length of `ifft` does not match the lane length along `axis`,
+/// if `ifft` is not an inverse plan, or if `v` or `work` are too small.
+pub fn ifft2d_with_ip_transpose<T: FftNum>(
+    v: &mut [Complex<T>],
+    work: &mut [Complex<T>],
+    m: usize,
+    n: usize,
+    ifft: &Arc<dyn Fft<T>>,
+    axis: usize,
+    norm: Normalization,
+) {
+    assert_direction(ifft, FftDirection::Inverse);
+    fft2d_axis_ip(v, work, m, n, ifft, axis, norm);
+}
+
+/// Minimum work-space of [`fft2d_with_ip_transpose`] with plan `fft`
+///
+/// The peak extra memory of the low-memory transform is the size of the
+/// provided work-space, i.e. at least this many elements.
+pub fn ip_workspace_len<T: FftNum>(fft: &Arc<dyn Fft<T>>) -> usize {
+    fft.get_inplace_scratch_len().max(2)
+}
+
+/// Fft along a single axis with in-place transposes
+fn fft2d_axis_ip<T: FftNum>(
+    v: &mut [Complex<T>],
+    work: &mut [Complex<T>],
+    m: usize,
+    n: usize,
+    fft: &Arc<dyn Fft<T>>,
+    axis: usize,
+    norm: Normalization,
+) {
+    assert!(v.len() == m * n, "{} != {}", v.len(), m * n);
+    assert!(axis < 2, "axis must be 0 or 1, got {}", axis);
+    let lane_len = if axis == 0 { m } else { n };
+    assert_length(fft, lane_len, axis);
+    assert!(
+        work.len() >= ip_workspace_len(fft),
+        "work-space too small, got {} expected at least {}",
+        work.len(),
+        ip_workspace_len(fft)
+    );
+    let scale = norm.factor(lane_len, fft.fft_direction());
+    if axis == 0 {
+        ip_transpose(v, work, m, n);
+        process_lanes_scaled(v, work, fft, scale);
+        ip_transpose(v, work, n, m);
+    } else {
+        process_lanes_scaled(v, work, fft, scale);
+    }
+}
+
+/// Multithreaded fft along `axis` of a flattened m x n array
+///
+/// Parallel version of [`fft2d_with_transpose_norm`]. The transposes are
+/// performed with [`oop_transpose_par`] and the contiguous lanes are split
+/// into batches, which are transformed on the current rayon thread pool.
+/// Each task allocates its own fft scratch.
+///
+/// # Arguments
+///
+/// * `v` - Flattened 2D array with m * n elements, input and output
+/// * `scratch` - Work-space for the transpose, at least m * n elements for
+///   `axis` = 0, unused for `axis` = 1. The fft scratch is allocated per task.
+/// * `m` - Number of rows
+/// * `n` - Number of cols
+/// * `fft` - Forward fft plan of length m (`axis` = 0) or n (`axis` = 1)
+/// * `axis` - Axis along which the fft is performed
+/// * `norm` - Normalization of the transform
+///
+/// # Panics
+///
+/// Panics if the length of `fft` does not match the lane length along `axis`,
+/// if `fft` is not a forward plan, or if `v` or `scratch` are too small.
+pub fn fft2d_with_transpose_par<T: FftNum>(
+    v: &mut [Complex<T>],
+    scratch: &mut [Complex<T>],
+    m: usize,
+    n: usize,
+    fft: &Arc<dyn Fft<T>>,
+    axis: usize,
+    norm: Normalization,
+) {
+    assert_direction(fft, FftDirection::Forward);
+    fft2d_axis_par(v, scratch, m, n, fft, axis, norm);
+}
+
+/// Multithreaded inverse fft along `axis` of a flattened m x n array
+///
+/// Counterpart of [`fft2d_with_transpose_par`].
+///
+/// # Arguments
+///
+/// * `v` - Flattened 2D array with m * n elements, input and output
+/// * `scratch` - Work-space for the transpose, at least m * n elements for
+///   `axis` = 0, unused for `axis` = 1. The fft scratch is allocated per task.
+/// * `m` - Number of rows
+/// * `n` - Number of cols
+/// * `ifft` - Inverse fft plan of length m (`axis` = 0) or n (`axis` = 1)
+/// * `axis` - Axis along which the fft is performed
+/// * `norm` - Normalization of the transform
+///
+/// # Panics
+///
+/// Panics if the length of `ifft` does not match the lane length along `axis`,
+/// if `ifft` is not an inverse plan, or if `v` or `scratch` are too small.
+pub fn ifft2d_with_transpose_par<T: FftNum>(
+    v: &mut [Complex<T>],
+    scratch: &mut [Complex<T>],
+    m: usize,
+    n: usize,
+    ifft: &Arc<dyn Fft<T>>,
+    axis: usize,
+    norm: Normalization,
+) {
+    assert_direction(ifft, FftDirection::Inverse);
+    fft2d_axis_par(v, scratch, m, n, ifft, axis, norm);
+}
+
+/// Multithreaded fft along a single axis in the direction of `fft`
+fn fft2d_axis_par<T: FftNum>(
+    v: &mut [Complex<T>],
+    scratch: &mut [Complex<T>],
+    m: usize,
+    n: usize,
+    fft: &Arc<dyn Fft<T>>,
+    axis: usize,
+    norm: Normalization,
+) {
+    assert!(v.len() == m * n, "{} != {}", v.len(), m * n);
+    assert!(axis < 2, "axis must be 0 or 1, got {}", axis);
+    let lane_len = if axis == 0 { m } else { n };
+    assert_length(fft, lane_len, axis);
+    let scale = norm.factor(lane_len, fft.fft_direction());
+    if axis == 0 {
+        assert!(scratch.len() >= m * n);
+        let scratch = &mut scratch[..m * n];
+        oop_transpose_par(v, scratch, m, n);
+        process_lanes_par(scratch, fft, 1.);
+        #[allow(clippy::float_cmp)]
+        if scale == 1. {
+            oop_transpose_par(scratch, v, n, m);
+        } else {
+            let scale = to_float::<T>(scale);
+            oop_transpose_par_map(scratch, v, n, m, |x| x * scale);
+        }
+    } else {
+        process_lanes_par(v, fft, scale);
+    }
+}
+
+/// Fft of all contiguous lanes of `data` in parallel batches, scaled by `scale`
+pub(crate) fn process_lanes_par<T: FftNum>(
+    data: &mut [Complex<T>],
+    fft: &Arc<dyn Fft<T>>,
+    scale: f64,
+) {
+    let len = fft.len();
+    if data.is_empty() || len == 0 {
+        return;
+    }
+    // A few batches per thread for load balancing
+    let lanes = data.len() / len;
+    let batch = (lanes / (4 * rayon::current_num_threads())).max(1);
+    let scratch_len = fft.get_inplace_scratch_len();
+    data.par_chunks_mut(batch * len).for_each_init(
+        || vec![Complex::zero(); scratch_len],
+        |scratch, chunk| {
+            fft.process_with_scratch(chunk, scratch);
+            scale_inplace(chunk, scale);
+        },
+    );
+}
+
+/// Real-to-complex fft along both axes of a flattened m x n array
+///
+/// A real-to-complex fft is performed along the contiguous axis 1,
+/// yielding the Hermitian half-spectrum of size m x (n / 2 + 1), which is then
+/// transformed along axis 0 with [`fft2d_with_transpose`]'s transpose method.
+/// Equivalent to numpy's `rfft2`.
+///
+/// # Arguments
+///
+/// * `v` - Flattened real 2D array with m * n elements, input. Used as
+///   work-space, its content is garbage after the call.
+/// * `vhat` - Flattened 2D array with m * (n / 2 + 1) elements, output
+/// * `scratch` - Work-space of at least m * (n / 2 + 1) + `fft_m.get_inplace_scratch_len()`
+///   and `r2c.get_scratch_len()` elements
+/// * `m` - Number of rows
+/// * `n` - Number of cols
+/// * `r2c` - Real-to-complex fft plan of length n
+/// * `fft_m` - Forward fft plan of length m
+/// * `norm` - Normalization of the transform
+///
+/// # Panics
+///
+/// Panics if the plans do not match the shape, if `fft_m` is not a forward plan,
+/// or if `v`, `vhat` or `scratch` are too small.
+#[allow(clippy::too_many_arguments)]
+pub fn rfft2d_with_transpose<T: FftNum>(
+    v: &mut [T],
+    vhat: &mut [Complex<T>],
+    scratch: &mut [Complex<T>],
+    m: usize,
+    n: usize,
+    r2c: &Arc<dyn RealToComplex<T>>,
+    fft_m: &Arc<dyn Fft<T>>,
+    norm: Normalization,
+) {
+    let h = n / 2 + 1;
+    assert!(v.len() == m * n, "{} != {}", v.len(), m * n);
+    assert!(vhat.len() == m * h, "{} != {}", vhat.len(), m * h);
+    assert!(
+        r2c.len() == n,
+        "Size mismatch in fft along axis 1, got {} expected {}",
+        r2c.len(),
+        n
+    );
+    assert!(
+        fft_m.len() == m,
+        "Size mismatch in fft along axis 0, got {} expected {}",
+        fft_m.len(),
+        m
+    );
+    assert_direction(fft_m, FftDirection::Forward);
+    let scratch_len = r2c.get_scratch_len();
+    let required = scratch_len.max(m * h + fft_m.get_inplace_scratch_len());
+    assert!(
+        scratch.len() >= required,
+        "{} < {}",
+        scratch.len(),
+        required
+    );
+    if m == 0 || n == 0 {
+        vhat.fill(Complex::zero());
+        return;
+    }
+    for (x, y) in v.chunks_exact_mut(n).zip(vhat.chunks_exact_mut(h)) {
+        r2c.process_with_scratch(x, y, &mut scratch[..scratch_len])
+            .expect("r2c buffers have lengths n, n / 2 + 1 and get_scratch_len()");
+    }
+    let scale = norm.factor(m * n, FftDirection::Forward);
+    fftnd_axis_scaled(vhat, scratch, &[m, h], fft_m, 0, scale);
+}
+
+/// Complex-to-real inverse fft along both axes of a flattened m x n array
+///
+/// Counterpart of [`rfft2d_with_transpose`]. The inverse fft along axis 0
+/// is performed on the Hermitian half-spectrum of size m x (n / 2 + 1), followed by
+/// a complex-to-real fft along axis 1. Equivalent to numpy's `irfft2`.
+///
+/// # Arguments
+///
+/// * `vhat` - Flattened 2D array with m * (n / 2 + 1) elements, input. Used as
+///   work-space, its content is garbage after the call.
+/// * `v` - Flattened real 2D array with m * n elements, output
+/// * `scratch` - Work-space of at least m * (n / 2 + 1) + `ifft_m.get_inplace_scratch_len()`
+///   and `c2r.get_scratch_len()` elements
+/// * `m` - Number of rows
+/// * `n` - Number of cols
+/// * `c2r` - Complex-to-real fft plan of length n
+/// * `ifft_m` - Inverse fft plan of length m
+/// * `norm` - Normalization of the transform
+///
+/// # Panics
+///
+/// Panics if the plans do not match the shape, if `ifft_m` is not an inverse plan,
+/// or if `v`, `vhat` or `scratch` are too small.
+#[allow(clippy::too_many_arguments)]
+pub fn irfft2d_with_transpose<T: FftNum>(
+    vhat: &mut [Complex<T>],
+    v: &mut [T],
+    scratch: &mut [Complex<T>],
+    m: usize,
+    n: usize,
+    c2r: &Arc<dyn ComplexToReal<T>>,
+    ifft_m: &Arc<dyn Fft<T>>,
+    norm: Normalization,
+) {
+    let h = n / 2 + 1;
+    assert!(v.len() == m * n, "{} != {}", v.len(), m * n);
+    assert!(vhat.len() == m * h, "{} != {}", vhat.len(), m * h);
+    assert!(
+        c2r.len() == n,
+        "Size mismatch in fft along axis 1, got {} expected {}",
+        c2r.len(),
+        n
+    );
+    assert!(
+        ifft_m.len() == m,
+        "Size mismatch in fft along axis 0, got {} expected {}",
+        ifft_m.len(),
+        m
+    );
+    assert_direction(ifft_m, FftDirection::Inverse);
+    let scratch_len = c2r.get_scratch_len();
+    let required = scratch_len.max(m * h + ifft_m.get_inplace_scratch_len());
+    assert!(
+        scratch.len() >= required,
+        "{} < {}",
+        scratch.len(),
+        required
+    );
+    if m == 0 || n == 0 {
+        return;
+    }
+    let scale = norm.factor(m * n, FftDirection::Inverse);
+    fftnd_axis_scaled(vhat, scratch, &[m, h], ifft_m, 0, scale);
+    for (x, y) in vhat.chunks_exact_mut(h).zip(v.chunks_exact_mut(n)) {
+        // First element must be real, and the last one for even n
+        x[0].im = T::zero();
+        if n.is_multiple_of(2) {
+            x[h - 1].im = T::zero();
+        }
+        c2r.process_with_scratch(x, y, &mut scratch[..scratch_len])
+            .expect("c2r buffers have lengths n / 2 + 1, n and get_scratch_len()");
+    }
+}
+
+/// Out-of-place transpose, scaled by `scale`
+pub(crate) fn transpose_scaled<T: FftNum>(
+    src: &[Complex<T>],
+    dst: &mut [Complex<T>],
+    rows: usize,
+    cols: usize,
+    scale: f64,
+) {
+    #[allow(clippy::float_cmp)]
+    if scale == 1. {
+        oop_transpose(src, dst, rows, cols);
+    } else {
+        let scale = to_float::<T>(scale);
+        oop_transpose_map(src, dst, rows, cols, |x| x * scale);
+    }
+}
+
+/// Multiply data by `scale`, skipped if `scale` is 1
+pub(crate) fn scale_inplace<T: FftNum>(v: &mut [Complex<T>], scale: f64) {
+    #[allow(clippy::float_cmp)]
+    if scale != 1. {
+        let scale = to_float::<T>(scale);
+        for x in v.iter_mut() {
+            *x = *x * scale;
+        }
+    }
+}
+
+/// Convert a scaling factor to the float type of the transform
+pub(crate) fn to_float<T: FftNum>(x: f64) -> T {
+    T::from_f64(x).unwrap()
+}
+
+pub(crate) fn assert_length<T: FftNum>(fft: &Arc<dyn Fft<T>>, lane_len: usize, axis: usize) {
+    assert!(
+        fft.len() == lane_len,
+        "Size mismatch in fft along axis {}, got {} expected {}",
+        axis,
+        fft.len(),
+        lane_len
+    );
+}
+
+pub(crate) fn assert_direction<T: FftNum>(fft: &Arc<dyn Fft<T>>, direction: FftDirection) {
+    assert!(
+        fft.fft_direction() == direction,
+        "Expected {} fft plan, got {}",
+        direction,
+        fft.fft_direction()
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ndfft_with_ndrustfft::{
+        fft2d_r2c_with_ndrustfft, fft2d_with_ndrustfft, ifft2d_r2c_with_ndrustfft,
+    };
+    use crate::test_array::{approx_eq, test_vec};
+    use ndarray::{Array2, ArrayD, IxDyn};
+    use ndrustfft::{ndfft, ndifft, FftHandler, R2cFftHandler};
+    use realfft::RealFftPlanner;
+    use rustfft::FftPlanner;
+
+    #[test]
+    fn test_fft2d_rectangular() {
+        // (509, 3) and (3, 509) need more fft scratch than m * n
+        let shapes = [
+            (100, 384),
+            (257, 64),
+            (64, 257),
+            (7, 1),
+            (1, 7),
+            (509, 3),
+            (3, 509),
+        ];
+        let mut planner = FftPlanner::<f64>::new();
+
+        for (m, n) in shapes {
+            for axis in [0, 1] {
+                let lane_len = if axis == 0 { m } else { n };
+
+                // Ndrustfft
+                let v = Array2::from_shape_vec((m, n), test_vec(m * n)).unwrap();
+                let mut vhat = Array2::<Complex<f64>>::zeros((m, n));
+                let mut handler: FftHandler<f64> = FftHandler::new(lane_len);
+                ndfft(&v, &mut vhat, &mut handler, axis);
+
+                // Transpose
+                let fft = planner.plan_fft_forward(lane_len);
+                let scratch_len = m * n + fft.get_inplace_scratch_len();
+                let mut v = test_vec(m * n);
+                let mut scratch = vec![Complex::default(); scratch_len];
+                fft2d_with_transpose(&mut v, &mut scratch, m, n, &fft, axis);
+
+                assert!(
+                    approx_eq(&v, vhat.as_slice().unwrap()),
+                    "shape ({}, {}), axis {}",
+                    m,
+                    n,
+                    axis
+                );
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Size mismatch")]
+    fn test_fft2d_wrong_plan_length() {
+        let (m, n) = (100, 384);
+        let fft = FftPlanner::<f64>::new().plan_fft_forward(n);
+        let mut v = test_vec(m * n);
+        let mut scratch = vec![Complex::default(); m * n];
+        fft2d_with_transpose(&mut v, &mut scratch, m, n, &fft, 0);
+    }
+
+    #[test]
+    fn test_fft2d_full() {
+        let shapes = [(16, 16), (100, 384), (257, 64), (1, 7), (509, 3)];
+        let mut planner = FftPlanner::<f64>::new();
+
+        for (m, n) in shapes {
+            // Ndrustfft
+            let v = Array2::from_shape_vec((m, n), test_vec(m * n)).unwrap();
+            let mut vhat_n = Array2::<Complex<f64>>::zeros((m, n));
+            let mut vhat = Array2::<Complex<f64>>::zeros((m, n));
+            ndfft(&v, &mut vhat_n, &mut FftHandler::new(n), 1);
+            ndfft(&vhat_n, &mut vhat, &mut FftHandler::new(m), 0);
+
+            // Transpose
+            let (fft_m, fft_n) = (planner.plan_fft_forward(m), planner.plan_fft_forward(n));
+            let (ifft_m, ifft_n) = (planner.plan_fft_inverse(m), planner.plan_fft_inverse(n));
+            let scratch_len = m * n
+                + [&fft_m, &fft_n, &ifft_m, &ifft_n]
+                    .iter()
+                    .map(|f| f.get_inplace_scratch_len())
+                    .max()
+                    .unwrap();
+            let mut v = test_vec(m * n);
+            let mut scratch = vec![Complex::default(); scratch_len];
+            let norm = Normalization::Backward;
+            fft2d_full(&mut v, &mut scratch, m, n, &fft_m, &fft_n, norm);
+            assert!(approx_eq(&v, vhat.as_slice().unwrap()));
+
+            // Round trip
+            ifft2d_full(&mut v, &mut scratch, m, n, &ifft_m, &ifft_n, norm);
+            assert!(approx_eq(&v, &test_vec(m * n)));
+        }
+    }
+
+    #[test]
+    fn test_normalization_round_trip() {
+        let (m, n) = (12, 30);
+        let mut planner = FftPlanner::<f64>::new();
+        let norms = [
+            Normalization::Backward,
+            Normalization::Ortho,
+            Normalization::Forward,
+        ];
+
+        for norm in norms {
+            for axis in [0, 1] {
+                let lane_len = if axis == 0 { m } else { n };
+                let fft = planner.plan_fft_forward(lane_len);
+                let ifft = planner.plan_fft_inverse(lane_len);
+                let mut v = test_vec(m * n);
+                let scratch_len = m * n + fft.get_inplace_scratch_len();
+                let mut scratch = vec![Complex::default(); scratch_len];
+                fft2d_with_transpose_norm(&mut v, &mut scratch, m, n, &fft, axis, norm);
+
+                // Compare to unnormalized transform
+                let mut expected = test_vec(m * n);
+                fft2d_with_transpose(&mut expected, &mut scratch, m, n, &fft, axis);
+                let scale = norm.factor(lane_len, FftDirection::Forward);
+                let expected: Vec<Complex<f64>> = expected.iter().map(|x| x * scale).collect();
+                assert!(approx_eq(&v, &expected));
+
+                ifft2d_with_transpose(&mut v, &mut scratch, m, n, &ifft, axis, norm);
+                assert!(approx_eq(&v, &test_vec(m * n)));
+            }
+
+            // Both axes
+            let (fft_m, fft_n) = (planner.plan_fft_forward(m), planner.plan_fft_forward(n));
+            let (ifft_m, ifft_n) = (planner.plan_fft_inverse(m), planner.plan_fft_inverse(n));
+            let mut v = test_vec(m * n);
+            let scratch_len = m * n
+                + fft_m
+                    .get_inplace_scratch_len()
+                    .max(fft_n.get_inplace_scratch_len());
+            let mut scratch = vec![Complex::default(); scratch_len];
+            fft2d_full(&mut v, &mut scratch, m, n, &fft_m, &fft_n, norm);
+            ifft2d_full(&mut v, &mut scratch, m, n, &ifft_m, &ifft_n, norm);
+            assert!(approx_eq(&v, &test_vec(m * n)));
+        }
+    }
+
+    #[test]
+    fn test_fft2d_inverse_plan() {
+        // Without normalization the direction follows the plan
+        let (m, n) = (12, 30);
+        let mut planner = FftPlanner::<f64>::new();
+        for axis in [0, 1] {
+            let lane_len = if axis == 0 { m } else { n };
+            let fft = planner.plan_fft_forward(lane_len);
+            let ifft = planner.plan_fft_inverse(lane_len);
+            let mut v = test_vec(m * n);
+            let mut scratch = vec![Complex::default(); m * n + fft.get_inplace_scratch_len()];
+            fft2d_with_transpose(&mut v, &mut scratch, m, n, &fft, axis);
+            fft2d_with_transpose(&mut v, &mut scratch, m, n, &ifft, axis);
+            assert!(approx_eq(&v, &test_vec(m * n)));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Expected Forward fft plan")]
+    fn test_fft2d_wrong_direction() {
+        let (m, n) = (8, 8);
+        let ifft = FftPlanner::<f64>::new().plan_fft_inverse(n);
+        let mut v = test_vec(m * n);
+        let mut scratch = vec![Complex::default(); m * n];
+        fft2d_with_transpose_norm(
+            &mut v,
+            &mut scratch,
+            m,
+            n,
+            &ifft,
+            1,
+            Normalization::Backward,
+        );
+    }
+
+    #[test]
+    fn test_fftnd() {
+        let shapes: [&[usize]; 4] = [&[6, 10, 14], &[5, 4, 3, 6], &[9, 1, 5], &[30]];
+        let mut planner = FftPlanner::<f64>::new();
+
+        for shape in shapes {
+            let size: usize = shape.iter().product();
+            for axis in 0..shape.len() {
+                // Ndrustfft
+                let v = ArrayD::from_shape_vec(IxDyn(shape), test_vec(size)).unwrap();
+                let mut vhat = ArrayD::<Complex<f64>>::zeros(IxDyn(shape));
+                let mut handler: FftHandler<f64> = FftHandler::new(shape[axis]);
+                ndfft(&v, &mut vhat, &mut handler, axis);
+
+                // Transpose
+                let fft = planner.plan_fft_forward(shape[axis]);
+                let ifft = planner.plan_fft_inverse(shape[axis]);
+                let scratch_len = size + fft.get_inplace_scratch_len();
+                let mut v = test_vec(size);
+                let mut scratch = vec![Complex::default(); scratch_len];
+                let norm = Normalization::Backward;
+                fftnd_with_transpose(&mut v, &mut scratch, shape, &fft, axis, norm);
+                assert!(
+                    approx_eq(&v, vhat.as_slice().unwrap()),
+                    "shape {:?}, axis {}",
+                    shape,
+                    axis
+                );
+
+                ifftnd_with_transpose(&mut v, &mut scratch, shape, &ifft, axis, norm);
+                assert!(approx_eq(&v, &test_vec(size)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_fftnd_empty() {
+        let mut planner = FftPlanner::<f64>::new();
+        for shape in [&[0, 4][..], &[3, 0, 5], &[2, 3, 0]] {
+            for axis in 0..shape.len() {
+                let fft = planner.plan_fft_forward(shape[axis]);
+                let mut scratch = vec![Complex::default(); fft.get_inplace_scratch_len()];
+                let norm = Normalization::Ortho;
+                fftnd_with_transpose(&mut [], &mut scratch, shape, &fft, axis, norm);
+            }
+        }
+    }
+
+    #[test]
+    fn test_rfft2d() {
+        let shapes = [(16, 16), (100, 384), (257, 64), (15, 33), (1, 7)];
+        let mut planner = FftPlanner::<f64>::new();
+        let mut real_planner = RealFftPlanner::<f64>::new();
+
+        for (m, n) in shapes {
+            let h = n / 2 + 1;
+            let data: Vec<f64> = test_vec(m * n).iter().map(|x| x.re).collect();
+
+            // Ndrustfft
+            let v = Array2::from_shape_vec((m, n), data.clone()).unwrap();
+            let mut vhat_n = Array2::<Complex<f64>>::zeros((m, h));
+            let mut vhat = Array2::<Complex<f64>>::zeros((m, h));
+            let mut handler_n = R2cFftHandler::<f64>::new(n);
+            let mut handler_m: FftHandler<f64> = FftHandler::new(m);
+            fft2d_r2c_with_ndrustfft(&v, &mut vhat_n, &mut handler_n, 1);
+            fft2d_with_ndrustfft(&vhat_n, &mut vhat, &mut handler_m, 0);
+
+            // Transpose
+            let (r2c, c2r) = (
+                real_planner.plan_fft_forward(n),
+                real_planner.plan_fft_inverse(n),
+            );
+            let (fft_m, ifft_m) = (planner.plan_fft_forward(m), planner.plan_fft_inverse(m));
+            let scratch_len = (m * h + fft_m.get_inplace_scratch_len())
+                .max(r2c.get_scratch_len())
+                .max(c2r.get_scratch_len());
+            let mut v = data.clone();
+            let mut vhat_t = vec![Complex::default(); m * h];
+            let mut scratch = vec![Complex::default(); scratch_len];
+            let norm = Normalization::Backward;
+            rfft2d_with_transpose(&mut v, &mut vhat_t, &mut scratch, m, n, &r2c, &fft_m, norm);
+            assert!(
+                approx_eq(&vhat_t, vhat.as_slice().unwrap()),
+                "shape ({}, {})",
+                m,
+                n
+            );
+
+            // Round trip
+            irfft2d_with_transpose(&mut vhat_t, &mut v, &mut scratch, m, n, &c2r, &ifft_m, norm);
+            let scale = data.iter().fold(1., |a: f64, b| a.max(b.abs()));
+            for (a, b) in v.iter().zip(data.iter()) {
+                assert!((a - b).abs() < 1e-10 * scale);
+            }
+
+            // Ndrustfft round trip
+            let mut w = Array2::<f64>::zeros((m, n));
+            ndifft(&vhat, &mut vhat_n, &mut handler_m, 0);
+            ifft2d_r2c_with_ndrustfft(&vhat_n, &mut w, &mut handler_n, 1);
+            for (a, b) in w.iter().zip(data.iter()) {
+                assert!((a - b).abs() < 1e-10 * scale);
+            }
+        }
+    }
+
+    #[test]
+    fn test_rfft2d_empty() {
+        let mut planner = FftPlanner::<f64>::new();
+        let mut real_planner = RealFftPlanner::<f64>::new();
+        for (m, n) in [(0, 4), (3, 0), (0, 0)] {
+            let h = n / 2 + 1;
+            let (r2c, c2r) = (
+                real_planner.plan_fft_forward(n),
+                real_planner.plan_fft_inverse(n),
+            );
+            let (fft_m, ifft_m) = (planner.plan_fft_forward(m), planner.plan_fft_inverse(m));
+            let scratch_len = (m * h + fft_m.get_inplace_scratch_len())
+                .max(r2c.get_scratch_len())
+                .max(c2r.get_scratch_len());
+            let mut v = vec![0.; m * n];
+            let mut vhat = vec![Complex::new(1., 1.); m * h];
+            let mut scratch = vec![Complex::default(); scratch_len];
+            let norm = Normalization::Backward;
+            rfft2d_with_transpose(&mut v, &mut vhat, &mut scratch, m, n, &r2c, &fft_m, norm);
+            assert!(vhat.iter().all(|x| *x == Complex::default()));
+            irfft2d_with_transpose(&mut vhat, &mut v, &mut scratch, m, n, &c2r, &ifft_m, norm);
+        }
+    }
+
+    #[test]
+    fn test_fft2d_layout() {
+        let shapes = [(100, 384), (257, 64), (12, 30)];
+        let mut planner = FftPlanner::<f64>::new();
+
+        for (m, n) in shapes {
+            let (fft_m, fft_n) = (planner.plan_fft_forward(m), planner.plan_fft_forward(n));
+            let (ifft_m, ifft_n) = (planner.plan_fft_inverse(m), planner.plan_fft_inverse(n));
+            let scratch_len = m * n
+                + [&fft_m, &fft_n, &ifft_m, &ifft_n]
+                    .iter()
+                    .map(|f| {
+                        f.get_inplace_scratch_len()
+                            .max(f.get_outofplace_scratch_len())
+                    })
+                    .max()
+                    .unwrap();
+            let mut scratch = vec![Complex::default(); scratch_len];
+            let norm = Normalization::Ortho;
+
+            // Along axis 0
+            let mut expected = test_vec(m * n);
+            fft2d_with_transpose_norm(&mut expected, &mut scratch, m, n, &fft_m, 0, norm);
+            let expected = Array2::from_shape_vec((m, n), expected).unwrap();
+
+            let mut v = test_vec(m * n);
+            let layout = Layout::Transposed;
+            let out = fft2d_with_layout(&mut v, &mut scratch, m, n, &fft_m, 0, norm, layout);
+            assert!(out == Layout::Transposed);
+            let v_t = Array2::from_shape_vec((n, m), v.clone()).unwrap();
+            assert!(approx_eq(
+                v_t.t().as_standard_layout().as_slice().unwrap(),
+                expected.as_slice().unwrap()
+            ));
+            ifft2d_with_layout(&mut v, &mut scratch, m, n, &ifft_m, 0, norm, out);
+            assert!(approx_eq(&v, &test_vec(m * n)));
+
+            // Along axis 1 the layout stays natural
+            let mut v = test_vec(m * n);
+            let out = fft2d_with_layout(&mut v, &mut scratch, m, n, &fft_n, 1, norm, layout);
+            assert!(out == Layout::Natural);
+            ifft2d_with_layout(&mut v, &mut scratch, m, n, &ifft_n, 1, norm, out);
+            assert!(approx_eq(&v, &test_vec(m * n)));
+
+            // Both axes
+            let mut expected = test_vec(m * n);
+            fft2d_full(&mut expected, &mut scratch, m, n, &fft_m, &fft_n, norm);
+            let expected = Array2::from_shape_vec((m, n), expected).unwrap();
+
+            let mut v = test_vec(m * n);
+            let out =
+                fft2d_full_with_layout(&mut v, &mut scratch, m, n, &fft_m, &fft_n, norm, layout);
+            assert!(out == Layout::Transposed);
+            let v_t = Array2::from_shape_vec((n, m), v.clone()).unwrap();
+            assert!(approx_eq(
+                v_t.t().as_standard_layout().as_slice().unwrap(),
+                expected.as_slice().unwrap()
+            ));
+            ifft2d_full_with_layout(&mut v, &mut scratch, m, n, &ifft_m, &ifft_n, norm, out);
+            assert!(approx_eq(&v, &test_vec(m * n)));
+        }
+    }
+
+    #[test]
+    fn test_fft2d_ip_transpose() {
+        let norm = Normalization::Ortho;
+        let mut planner = FftPlanner::<f64>::new();
+        for (m, n) in [(100, 384), (257, 64), (7, 1)] {
+            for axis in [0, 1] {
+                let lane_len = if axis == 0 { m } else { n };
+                let fft = planner.plan_fft_forward(lane_len);
+                let ifft = planner.plan_fft_inverse(lane_len);
+                let mut expected = test_vec(m * n);
+                let scratch_len = m * n + fft.get_inplace_scratch_len();
+                let mut scratch = vec![Complex::default(); scratch_len];
+                fft2d_with_transpose_norm(&mut expected, &mut scratch, m, n, &fft, axis, norm);
+
+                // Minimum and a larger work-space
+                for work_len in [ip_workspace_len(&fft), 1000] {
+                    let mut work = vec![Complex::default(); work_len];
+                    let mut v = test_vec(m * n);
+                    fft2d_with_ip_transpose(&mut v, &mut work, m, n, &fft, axis, norm);
+                    assert!(approx_eq(&v, &expected));
+                    ifft2d_with_ip_transpose(&mut v, &mut work, m, n, &ifft, axis, norm);
+                    assert!(approx_eq(&v, &test_vec(m * n)));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_fft2d_par() {
+        let norm = Normalization::Ortho;
+        let mut planner = FftPlanner::<f64>::new();
+        for (m, n) in [(100, 384), (257, 64), (7, 1)] {
+            for axis in [0, 1] {
+                let lane_len = if axis == 0 { m } else { n };
+                let fft = planner.plan_fft_forward(lane_len);
+                let ifft = planner.plan_fft_inverse(lane_len);
+                let mut expected = test_vec(m * n);
+                let scratch_len = m * n + fft.get_inplace_scratch_len();
+                let mut scratch = vec![Complex::default(); scratch_len];
+                fft2d_with_transpose_norm(&mut expected, &mut scratch, m, n, &fft, axis, norm);
+
+                let mut v = test_vec(m * n);
+                fft2d_with_transpose_par(&mut v, &mut scratch, m, n, &fft, axis, norm);
+                assert!(approx_eq(&v, &expected));
+                ifft2d_with_transpose_par(&mut v, &mut scratch, m, n, &ifft, axis, norm);
+                assert!(approx_eq(&v, &test_vec(m * n)));
+            }
+        }
     }
 }
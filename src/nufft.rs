@@ -0,0 +1,466 @@
+//! Non-uniform ffts of type 1 and 2 in 2D
+//!
+//! For points x_j = (x_j, y_j) in the 2 pi periodic plane and the modes
+//! k = (k0, k1) with -N0 / 2 <= k0 < N0 / 2 and -N1 / 2 <= k1 < N1 / 2:
+//!
+//! - Type 1 (non-uniform to uniform): f_k = sum_j c_j exp(± i k·x_j)
+//! - Type 2 (uniform to non-uniform): c_j = sum_k f_k exp(± i k·x_j)
+//!
+//! where the sign is - for [`FftDirection::Forward`] and + for
+//! [`FftDirection::Inverse`]. The modes are ordered with increasing k, like
+//! [`crate::freq::Spectrum::Shifted`].
+//!
+//! The transforms follow the spreading approach: the points are spread to
+//! (type 1) or interpolated from (type 2) a fine uniform grid with twice the
+//! number of modes along each axis, using the "exponential of semicircle"
+//! kernel exp(beta (sqrt(1 - z²) - 1)) of w points width. The fine grid
+//! is transformed with [`Fft2dPlan`], and the kernel is deconvolved with its
+//! Fourier transform, evaluated by Gauss-Legendre quadrature. The width is
+//! chosen from the requested tolerance.
+//!
+//! [`nudft2d_type1`] and [`nudft2d_type2`] evaluate the sums directly, at
+//! O(N M) cost, as reference for accuracy tests.
+//!
+//! # Example
+//! ```
+//! use ndfft_test::nufft::Nufft2d;
+//! use rustfft::{num_complex::Complex, FftDirection};
+//!
+//! let points = [[0.1, -2.], [3., 1.5], [-0.7, 0.2]];
+//! let strengths = vec![Complex::new(1., 0.); points.len()];
+//! let mut nufft = Nufft2d::new((16, 12), FftDirection::Forward, 1e-9);
+//! let mut modes = vec![Complex::new(0., 0.); 16 * 12];
+//! nufft.type1(&points, &strengths, &mut modes);
+//! ```
+use crate::ndfft_with_transpose::Normalization;
+use crate::plan::Fft2dPlan;
+use rustfft::{num_complex::Complex, FftDirection};
+use std::f64::consts::PI;
+
+/// Oversampling factor of the fine grid
+const SIGMA: usize = 2;
+
+/// Largest kernel width
+const MAX_WIDTH: usize = 16;
+
+/// Reusable 2D non-uniform fft of type 1 and 2
+pub struct Nufft2d {
+    /// Number of modes along both axes
+    modes: (usize, usize),
+    /// Size of the fine grid
+    fine: (usize, usize),
+    direction: FftDirection,
+    /// Kernel width in grid points
+    width: usize,
+    /// Kernel shape parameter
+    beta: f64,
+    /// Deconvolution factors of the modes along both axes
+    correction: (Vec<f64>, Vec<f64>),
+    plan: Fft2dPlan<f64>,
+    grid: Vec<Complex<f64>>,
+}
+
+impl Nufft2d {
+    /// Plan non-uniform ffts with `modes` uniform modes, the sign of the
+    /// exponent given by `direction` and a relative accuracy of about
+    /// `tolerance`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `tolerance` is not positive.
+    pub fn new(modes: (usize, usize), direction: FftDirection, tolerance: f64) -> Self {
+        assert!(tolerance > 0., "tolerance must be positive");
+        let width = ((1. / tolerance).log10().ceil() as usize + 1).clamp(2, MAX_WIDTH);
+        let beta = 2.3 * width as f64;
+        let fine = (fine_size(modes.0, width), fine_size(modes.1, width));
+        let correction = (
+            correction(modes.0, fine.0, width, beta),
+            correction(modes.1, fine.1, width, beta),
+        );
+        // Both directions unscaled
+        let norm = match direction {
+            FftDirection::Forward => Normalization::Backward,
+            FftDirection::Inverse => Normalization::Forward,
+        };
+        Self {
+            modes,
+            fine,
+            direction,
+            width,
+            beta,
+            correction,
+            plan: Fft2dPlan::full(fine, direction).normalization(norm),
+            grid: vec![Complex::default(); fine.0 * fine.1],
+        }
+    }
+
+    /// Number of modes along both axes
+    pub fn modes(&self) -> (usize, usize) {
+        self.modes
+    }
+
+    /// Sign of the exponent
+    pub fn direction(&self) -> FftDirection {
+        self.direction
+    }
+
+    /// Size of the oversampled fine grid
+    pub fn fine_shape(&self) -> (usize, usize) {
+        self.fine
+    }
+
+    /// Kernel width in grid points
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Type 1 transform, from `strengths` at `points` to the uniform `modes`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `strengths` does not match `points` or `modes` does not
+    /// have N0 * N1 elements.
+    pub fn type1(
+        &mut self,
+        points: &[[f64; 2]],
+        strengths: &[Complex<f64>],
+        modes: &mut [Complex<f64>],
+    ) {
+        let (n0, n1) = self.modes;
+        assert!(
+            strengths.len() == points.len(),
+            "{} != {}",
+            strengths.len(),
+            points.len()
+        );
+        assert!(modes.len() == n0 * n1, "{} != {}", modes.len(), n0 * n1);
+        let (nf0, nf1) = self.fine;
+        self.grid.fill(Complex::default());
+        let mut k0 = vec![0.; self.width];
+        let mut k1 = vec![0.; self.width];
+        for (point, c) in points.iter().zip(strengths) {
+            let i0 = self.kernel_values(point[0], nf0, &mut k0);
+            let i1 = self.kernel_values(point[1], nf1, &mut k1);
+            for (a, w0) in k0.iter().enumerate() {
+                let row = &mut self.grid[((i0 + a) % nf0) * nf1..][..nf1];
+                let cw = c * w0;
+                for (b, w1) in k1.iter().enumerate() {
+                    row[(i1 + b) % nf1] += cw * w1;
+                }
+            }
+        }
+        self.plan.process(&mut self.grid);
+        for (i, (row, p0)) in modes
+            .chunks_exact_mut(n1)
+            .zip(&self.correction.0)
+            .enumerate()
+        {
+            let fine_row = &self.grid[mode_index(i, n0, nf0) * nf1..][..nf1];
+            for (j, (f, p1)) in row.iter_mut().zip(&self.correction.1).enumerate() {
+                *f = fine_row[mode_index(j, n1, nf1)] * (p0 * p1);
+            }
+        }
+    }
+
+    /// Type 2 transform, from the uniform `modes` to `values` at `points`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `values` does not match `points` or `modes` does not have
+    /// N0 * N1 elements.
+    pub fn type2(
+        &mut self,
+        points: &[[f64; 2]],
+        modes: &[Complex<f64>],
+        values: &mut [Complex<f64>],
+    ) {
+        let (n0, n1) = self.modes;
+        assert!(
+            values.len() == points.len(),
+            "{} != {}",
+            values.len(),
+            points.len()
+        );
+        assert!(modes.len() == n0 * n1, "{} != {}", modes.len(), n0 * n1);
+        let (nf0, nf1) = self.fine;
+        self.grid.fill(Complex::default());
+        for (i, (row, p0)) in modes.chunks_exact(n1).zip(&self.correction.0).enumerate() {
+            let fine_row = &mut self.grid[mode_index(i, n0, nf0) * nf1..][..nf1];
+            for (j, (f, p1)) in row.iter().zip(&self.correction.1).enumerate() {
+                fine_row[mode_index(j, n1, nf1)] = f * (p0 * p1);
+            }
+        }
+        self.plan.process(&mut self.grid);
+        let mut k0 = vec![0.; self.width];
+        let mut k1 = vec![0.; self.width];
+        for (point, c) in points.iter().zip(values.iter_mut()) {
+            let i0 = self.kernel_values(point[0], nf0, &mut k0);
+            let i1 = self.kernel_values(point[1], nf1, &mut k1);
+            let mut sum = Complex::default();
+            for (a, w0) in k0.iter().enumerate() {
+                let row = &self.grid[((i0 + a) % nf0) * nf1..][..nf1];
+                let mut row_sum = Complex::default();
+                for (b, w1) in k1.iter().enumerate() {
+                    row_sum += row[(i1 + b) % nf1] * w1;
+                }
+                sum += row_sum * w0;
+            }
+            *c = sum;
+        }
+    }
+
+    /// Kernel values at the `width` fine grid points next to `x`, written to
+    /// `values`, returns the index of the first of these points
+    fn kernel_values(&self, x: f64, nf: usize, values: &mut [f64]) -> usize {
+        let h = 2. * PI / nf as f64;
+        // Position in units of the grid spacing, in [0, nf)
+        let t = x.rem_euclid(2. * PI) / h;
+        let half = 0.5 * self.width as f64;
+        let first = (t - half).ceil();
+        for (a, v) in values.iter_mut().enumerate() {
+            let z = (first + a as f64 - t) / half;
+            *v = es_kernel(z, self.beta);
+        }
+        // Shift by nf to wrap negative indices
+        (first as isize).rem_euclid(nf as isize) as usize
+    }
+}
+
+/// Exponential of semicircle kernel on [-1, 1]
+fn es_kernel(z: f64, beta: f64) -> f64 {
+    if z.abs() >= 1. {
+        0.
+    } else {
+        (beta * ((1. - z * z).sqrt() - 1.)).exp()
+    }
+}
+
+/// Index on a fine grid of size `nf` of the i-th mode, in increasing order,
+/// of `n` modes
+fn mode_index(i: usize, n: usize, nf: usize) -> usize {
+    (i + nf - n / 2) % nf
+}
+
+/// Even size of the fine grid, at least `SIGMA` * n and twice the kernel width
+fn fine_size(n: usize, width: usize) -> usize {
+    let size = (SIGMA * n).max(2 * width);
+    size + size % 2
+}
+
+/// Deconvolution factors h / psi_hat(k) of the `n` modes in increasing order
+///
+/// The kernel psi(x) = phi(x / alpha) with half-width alpha = w h / 2 has
+/// the Fourier transform psi_hat(k) = alpha int_-1^1 phi(z) cos(k alpha z) dz.
+fn correction(n: usize, nf: usize, width: usize, beta: f64) -> Vec<f64> {
+    let (nodes, weights) = gauss_legendre(2 * width + 8);
+    let h = 2. * PI / nf as f64;
+    let alpha = 0.5 * width as f64 * h;
+    let phi: Vec<f64> = nodes.iter().map(|z| es_kernel(*z, beta)).collect();
+    (0..n)
+        .map(|i| {
+            let k = i as f64 - (n / 2) as f64;
+            let integral: f64 = nodes
+                .iter()
+                .zip(&weights)
+                .zip(&phi)
+                .map(|((z, w), p)| w * p * (k * alpha * z).cos())
+                .sum();
+            h / (alpha * integral)
+        })
+        .collect()
+}
+
+/// Nodes and weights of the `q` point Gauss-Legendre quadrature on [-1, 1]
+fn gauss_legendre(q: usize) -> (Vec<f64>, Vec<f64>) {
+    let mut nodes = vec![0.; q];
+    let mut weights = vec![0.; q];
+    for i in 0..q {
+        // Newton iteration from the asymptotic guess of the i-th root
+        let mut x = (PI * (i as f64 + 0.75) / (q as f64 + 0.5)).cos();
+        let mut dp = 1.;
+        for _ in 0..100 {
+            let (p, d) = legendre(q, x);
+            dp = d;
+            let dx = p / d;
+            x -= dx;
+            if dx.abs() < 1e-16 {
+                break;
+            }
+        }
+        nodes[i] = x;
+        weights[i] = 2. / ((1. - x * x) * dp * dp);
+    }
+    (nodes, weights)
+}
+
+/// Legendre polynomial P_q(x) and its derivative
+fn legendre(q: usize, x: f64) -> (f64, f64) {
+    let (mut p0, mut p1) = (1., x);
+    for k in 2..=q {
+        let k = k as f64;
+        let p2 = ((2. * k - 1.) * x * p1 - (k - 1.) * p0) / k;
+        p0 = p1;
+        p1 = p2;
+    }
+    if q == 0 {
+        return (1., 0.);
+    }
+    let d = q as f64 * (x * p1 - p0) / (x * x - 1.);
+    (p1, d)
+}
+
+/// Sign of the exponent of `direction`
+fn sign(direction: FftDirection) -> f64 {
+    match direction {
+        FftDirection::Forward => -1.,
+        FftDirection::Inverse => 1.,
+    }
+}
+
+/// Direct evaluation of the type 1 transform, f_k = sum_j c_j exp(± i k·x_j)
+///
+/// # Panics
+///
+/// Panics if `strengths` does not match `points`.
+pub fn nudft2d_type1(
+    points: &[[f64; 2]],
+    strengths: &[Complex<f64>],
+    modes: (usize, usize),
+    direction: FftDirection,
+) -> Vec<Complex<f64>> {
+    assert!(
+        strengths.len() == points.len(),
+        "{} != {}",
+        strengths.len(),
+        points.len()
+    );
+    let s = sign(direction);
+    let (n0, n1) = modes;
+    let mut f = vec![Complex::default(); n0 * n1];
+    for i in 0..n0 {
+        let k0 = i as f64 - (n0 / 2) as f64;
+        for j in 0..n1 {
+            let k1 = j as f64 - (n1 / 2) as f64;
+            f[i * n1 + j] = points
+                .iter()
+                .zip(strengths)
+                .map(|(x, c)| c * Complex::from_polar(1., s * (k0 * x[0] + k1 * x[1])))
+                .sum();
+        }
+    }
+    f
+}
+
+/// Direct evaluation of the type 2 transform, c_j = sum_k f_k exp(± i k·x_j)
+///
+/// # Panics
+///
+/// Panics if `modes` does not have N0 * N1 elements.
+pub fn nudft2d_type2(
+    points: &[[f64; 2]],
+    coefficients: &[Complex<f64>],
+    modes: (usize, usize),
+    direction: FftDirection,
+) -> Vec<Complex<f64>> {
+    let (n0, n1) = modes;
+    assert!(
+        coefficients.len() == n0 * n1,
+        "{} != {}",
+        coefficients.len(),
+        n0 * n1
+    );
+    let s = sign(direction);
+    points
+        .iter()
+        .map(|x| {
+            let mut sum = Complex::default();
+            for i in 0..n0 {
+                let k0 = i as f64 - (n0 / 2) as f64;
+                for j in 0..n1 {
+                    let k1 = j as f64 - (n1 / 2) as f64;
+                    sum += coefficients[i * n1 + j]
+                        * Complex::from_polar(1., s * (k0 * x[0] + k1 * x[1]));
+                }
+            }
+            sum
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Deterministic pseudo-random numbers in [0, 1)
+    fn random(n: usize, seed: u64) -> Vec<f64> {
+        let mut state = seed;
+        (0..n)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6_364_136_223_846_793_005)
+                    .wrapping_add(1_442_695_040_888_963_407);
+                (state >> 11) as f64 / (1_u64 << 53) as f64
+            })
+            .collect()
+    }
+
+    fn relative_error(result: &[Complex<f64>], expected: &[Complex<f64>]) -> f64 {
+        let diff: f64 = result
+            .iter()
+            .zip(expected)
+            .map(|(a, b)| (a - b).norm_sqr())
+            .sum();
+        let norm: f64 = expected.iter().map(Complex::norm_sqr).sum();
+        (diff / norm).sqrt()
+    }
+
+    fn complex(r: &[f64]) -> Vec<Complex<f64>> {
+        r.chunks_exact(2)
+            .map(|p| Complex::new(p[0] - 0.5, p[1] - 0.5))
+            .collect()
+    }
+
+    #[test]
+    fn test_gauss_legendre() {
+        let (nodes, weights) = gauss_legendre(6);
+        // Exact for polynomials up to degree 11
+        let integral: f64 = nodes
+            .iter()
+            .zip(&weights)
+            .map(|(x, w)| w * x.powi(10))
+            .sum();
+        assert!((integral - 2. / 11.).abs() < 1e-14);
+        assert!((weights.iter().sum::<f64>() - 2.).abs() < 1e-14);
+    }
+
+    #[test]
+    fn test_nufft2d() {
+        let modes = (12, 9);
+        let n_points = 60;
+        let r = random(4 * n_points + 2 * modes.0 * modes.1, 42);
+        // Points partly outside [-pi, pi), which wrap periodically
+        let points: Vec<[f64; 2]> = r[..2 * n_points]
+            .chunks_exact(2)
+            .map(|p| [2.2 * PI * (p[0] - 0.5), 2. * PI * (p[1] - 0.5)])
+            .collect();
+        let strengths = complex(&r[2 * n_points..4 * n_points]);
+        let coefficients = complex(&r[4 * n_points..]);
+        for tolerance in [1e-6, 1e-11] {
+            for direction in [FftDirection::Forward, FftDirection::Inverse] {
+                let mut nufft = Nufft2d::new(modes, direction, tolerance);
+
+                let mut f = vec![Complex::default(); modes.0 * modes.1];
+                nufft.type1(&points, &strengths, &mut f);
+                let expected = nudft2d_type1(&points, &strengths, modes, direction);
+                let error = relative_error(&f, &expected);
+                assert!(error < 10. * tolerance, "type 1: {} > {}", error, tolerance);
+
+                let mut c = vec![Complex::default(); points.len()];
+                nufft.type2(&points, &coefficients, &mut c);
+                let expected = nudft2d_type2(&points, &coefficients, modes, direction);
+                let error = relative_error(&c, &expected);
+                assert!(error < 10. * tolerance, "type 2: {} > {}", error, tolerance);
+            }
+        }
+    }
+}
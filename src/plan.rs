@@ -0,0 +1,411 @@
+//! Reusable plan for 2D ffts with the transpose method
+//!
+//! Bundles the fft plans, the scratch buffer and the shape, so that call sites
+//! do not have to size the scratch themselves.
+//!
+//! # Example
+//! ```
+//! use ndfft_test::plan::Fft2dPlan;
+//! use rustfft::num_complex::Complex;
+//! use rustfft::FftDirection;
+//!
+//! let (m, n) = (6, 4);
+//! let mut data = vec![Complex::new(1., 0.); m * n];
+//! let mut plan = Fft2dPlan::new((m, n), 0, FftDirection::Forward);
+//! plan.process(&mut data);
+//! ```
+use crate::ndfft_with_transpose::{
+    fft2d_full_with_layout, fft2d_with_ip_transpose, fft2d_with_layout, fft2d_with_transpose_par,
+    ifft2d_full_with_layout, ifft2d_with_ip_transpose, ifft2d_with_layout,
+    ifft2d_with_transpose_par, ip_workspace_len, Layout, Normalization,
+};
+use rustfft::{num_complex::Complex, num_traits::Zero, Fft, FftDirection, FftNum, FftPlanner};
+use std::sync::Arc;
+
+/// Planned 2D fft of a flattened m x n array along one or both axes
+///
+/// Generic over the float type of the data, `f64` by default.
+pub struct Fft2dPlan<T: FftNum = f64> {
+    m: usize,
+    n: usize,
+    /// Axis of the transform, `None` for both axes
+    axis: Option<usize>,
+    direction: FftDirection,
+    norm: Normalization,
+    /// Layout of the output (forward) or input (inverse)
+    layout: Layout,
+    /// Work-space size of the low-memory mode, `None` if disabled
+    low_memory: Option<usize>,
+    /// Distribute the work over the rayon thread pool
+    parallel: bool,
+    fft_m: Arc<dyn Fft<T>>,
+    fft_n: Arc<dyn Fft<T>>,
+    scratch: Vec<Complex<T>>,
+}
+
+impl<T: FftNum> Fft2dPlan<T> {
+    /// Plan an fft along `axis` of a flattened array of size `shape`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `axis` is not 0 or 1.
+    pub fn new(shape: (usize, usize), axis: usize, direction: FftDirection) -> Self {
+        assert!(axis < 2, "axis must be 0 or 1, got {}", axis);
+        Self::with_axis(shape, Some(axis), direction)
+    }
+
+    /// Plan an fft along both axes of a flattened array of size `shape`
+    pub fn full(shape: (usize, usize), direction: FftDirection) -> Self {
+        Self::with_axis(shape, None, direction)
+    }
+
+    fn with_axis(shape: (usize, usize), axis: Option<usize>, direction: FftDirection) -> Self {
+        let (m, n) = shape;
+        let mut planner = FftPlanner::<T>::new();
+        let fft_m = planner.plan_fft(m, direction);
+        let fft_n = planner.plan_fft(n, direction);
+        let mut plan = Self {
+            m,
+            n,
+            axis,
+            direction,
+            norm: Normalization::default(),
+            layout: Layout::default(),
+            low_memory: None,
+            parallel: false,
+            fft_m,
+            fft_n,
+            scratch: vec![],
+        };
+        plan.scratch = vec![Complex::zero(); plan.required_scratch_len()];
+        plan
+    }
+
+    /// Scratch required for the current axis and layout
+    fn required_scratch_len(&self) -> usize {
+        let (m, n) = (self.m, self.n);
+        if let Some(work_len) = self.low_memory {
+            return match self.axis {
+                Some(0) => work_len.max(ip_workspace_len(&self.fft_m)),
+                Some(_) => ip_workspace_len(&self.fft_n),
+                None => work_len
+                    .max(ip_workspace_len(&self.fft_m))
+                    .max(ip_workspace_len(&self.fft_n)),
+            };
+        }
+        if self.parallel {
+            return if self.axis == Some(1) { 0 } else { m * n };
+        }
+        let m_inplace = self.fft_m.get_inplace_scratch_len();
+        let m_outofplace = self.fft_m.get_outofplace_scratch_len();
+        let n_inplace = self.fft_n.get_inplace_scratch_len();
+        match (self.axis, self.layout) {
+            (Some(0), Layout::Natural) => m * n + m_inplace,
+            (Some(0), Layout::Transposed) => m * n + m_outofplace,
+            (Some(_), _) => n_inplace,
+            (None, Layout::Natural) => (m * n + m_inplace).max(n_inplace),
+            (None, Layout::Transposed) => (m * n + m_outofplace).max(n_inplace),
+        }
+    }
+
+    /// Set the normalization, default is [`Normalization::Backward`]
+    #[must_use]
+    pub fn normalization(mut self, norm: Normalization) -> Self {
+        self.norm = norm;
+        self
+    }
+
+    /// Set the layout of the output of a forward, or the input of an
+    /// inverse transform, default is [`Layout::Natural`]
+    ///
+    /// A transposed layout skips one transpose along axis 0, see
+    /// [`fft2d_with_layout`]. It has no effect for transforms
+    /// along axis 1 only.
+    #[must_use]
+    pub fn layout(mut self, layout: Layout) -> Self {
+        self.layout = if self.axis == Some(1) {
+            Layout::Natural
+        } else {
+            layout
+        };
+        self.scratch = vec![Complex::zero(); self.required_scratch_len()];
+        self
+    }
+
+    /// Transpose in-place with a work-space of `work_len` elements instead
+    /// of using an m * n scratch, see [`fft2d_with_ip_transpose`]
+    ///
+    /// Trades speed for memory, larger work-spaces are faster. The work-space
+    /// is enlarged to the minimum required by the fft plans, see
+    /// [`Fft2dPlan::peak_memory`]. In low-memory mode the data is always in
+    /// natural layout, [`Fft2dPlan::layout`] has no effect.
+    #[must_use]
+    pub fn low_memory(mut self, work_len: usize) -> Self {
+        self.low_memory = Some(work_len);
+        self.scratch = vec![Complex::zero(); self.required_scratch_len()];
+        self
+    }
+
+    /// Run the transposes and ffts on the rayon thread pool, see
+    /// [`fft2d_with_transpose_par`]
+    ///
+    /// The data is always in natural layout, [`Fft2dPlan::layout`] has no
+    /// effect. The low-memory mode takes precedence.
+    #[must_use]
+    pub fn parallel(mut self, parallel: bool) -> Self {
+        self.parallel = parallel;
+        self.scratch = vec![Complex::zero(); self.required_scratch_len()];
+        self
+    }
+
+    /// Shape (m, n) of the array
+    pub fn shape(&self) -> (usize, usize) {
+        (self.m, self.n)
+    }
+
+    /// Axis of the transform, `None` if both axes are transformed
+    pub fn axis(&self) -> Option<usize> {
+        self.axis
+    }
+
+    /// Direction of the transform
+    pub fn direction(&self) -> FftDirection {
+        self.direction
+    }
+
+    /// Number of elements of the owned scratch buffer
+    pub fn scratch_len(&self) -> usize {
+        self.scratch.len()
+    }
+
+    /// Peak extra memory of [`Fft2dPlan::process`] in bytes
+    ///
+    /// This is the size of the scratch buffer owned by the plan. In parallel
+    /// mode, every thread of the current rayon pool additionally allocates the
+    /// in-place scratch of the fft during the call, which is included.
+    pub fn peak_memory(&self) -> usize {
+        let mut len = self.scratch.len();
+        if self.parallel && self.low_memory.is_none() {
+            let thread_len = match self.axis {
+                Some(0) => self.fft_m.get_inplace_scratch_len(),
+                Some(_) => self.fft_n.get_inplace_scratch_len(),
+                None => self
+                    .fft_m
+                    .get_inplace_scratch_len()
+                    .max(self.fft_n.get_inplace_scratch_len()),
+            };
+            len += rayon::current_num_threads() * thread_len;
+        }
+        len * std::mem::size_of::<Complex<T>>()
+    }
+
+    /// Transform `data` in-place
+    ///
+    /// For an inverse transform, `data` must be in the layout set
+    /// with [`Fft2dPlan::layout`].
+    ///
+    /// # Returns
+    ///
+    /// Layout of `data` after the transform
+    ///
+    /// # Panics
+    ///
+    /// Panics if `data` does not have m * n elements.
+    pub fn process(&mut self, data: &mut [Complex<T>]) -> Layout {
+        let (m, n, norm, layout) = (self.m, self.n, self.norm, self.layout);
+        let (fft_m, fft_n) = (&self.fft_m, &self.fft_n);
+        let scratch = &mut self.scratch;
+        if self.low_memory.is_some() || self.parallel {
+            let low_memory = self.low_memory.is_some();
+            let axes: &[(usize, &Arc<dyn Fft<T>>)] = match self.axis {
+                Some(0) => &[(0, fft_m)],
+                Some(_) => &[(1, fft_n)],
+                None => &[(1, fft_n), (0, fft_m)],
+            };
+            for &(axis, fft) in axes {
+                match (low_memory, self.direction) {
+                    (true, FftDirection::Forward) => {
+                        fft2d_with_ip_transpose(data, scratch, m, n, fft, axis, norm);
+                    }
+                    (true, FftDirection::Inverse) => {
+                        ifft2d_with_ip_transpose(data, scratch, m, n, fft, axis, norm);
+                    }
+                    (false, FftDirection::Forward) => {
+                        fft2d_with_transpose_par(data, scratch, m, n, fft, axis, norm);
+                    }
+                    (false, FftDirection::Inverse) => {
+                        ifft2d_with_transpose_par(data, scratch, m, n, fft, axis, norm);
+                    }
+                }
+            }
+            return Layout::Natural;
+        }
+        match (self.axis, self.direction) {
+            (Some(axis), FftDirection::Forward) => {
+                let fft = if axis == 0 { fft_m } else { fft_n };
+                fft2d_with_layout(data, scratch, m, n, fft, axis, norm, layout)
+            }
+            (Some(axis), FftDirection::Inverse) => {
+                let fft = if axis == 0 { fft_m } else { fft_n };
+                ifft2d_with_layout(data, scratch, m, n, fft, axis, norm, layout);
+                Layout::Natural
+            }
+            (None, FftDirection::Forward) => {
+                fft2d_full_with_layout(data, scratch, m, n, fft_m, fft_n, norm, layout)
+            }
+            (None, FftDirection::Inverse) => {
+                ifft2d_full_with_layout(data, scratch, m, n, fft_m, fft_n, norm, layout);
+                Layout::Natural
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_array::{approx_eq, test_vec};
+    use ndarray::Array2;
+    use ndrustfft::{ndfft, FftHandler};
+
+    #[test]
+    fn test_plan_axis() {
+        // (509, 3) needs more fft scratch than m * n along axis 0
+        for (m, n) in [(100, 384), (257, 64), (1, 7), (509, 3)] {
+            for axis in [0, 1] {
+                let lane_len = if axis == 0 { m } else { n };
+                let v = Array2::from_shape_vec((m, n), test_vec(m * n)).unwrap();
+                let mut vhat = Array2::<Complex<f64>>::zeros((m, n));
+                let mut handler: FftHandler<f64> = FftHandler::new(lane_len);
+                ndfft(&v, &mut vhat, &mut handler, axis);
+
+                let mut v = test_vec(m * n);
+                let mut plan = Fft2dPlan::new((m, n), axis, FftDirection::Forward);
+                plan.process(&mut v);
+                assert!(approx_eq(&v, vhat.as_slice().unwrap()));
+
+                let mut iplan = Fft2dPlan::new((m, n), axis, FftDirection::Inverse);
+                iplan.process(&mut v);
+                assert!(approx_eq(&v, &test_vec(m * n)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_plan_full() {
+        for (m, n) in [(30, 17), (509, 3)] {
+            let norm = Normalization::Ortho;
+            let mut plan = Fft2dPlan::full((m, n), FftDirection::Forward).normalization(norm);
+            let mut iplan = Fft2dPlan::full((m, n), FftDirection::Inverse).normalization(norm);
+            let mut v = test_vec(m * n);
+            plan.process(&mut v);
+            iplan.process(&mut v);
+            assert!(approx_eq(&v, &test_vec(m * n)));
+        }
+    }
+
+    #[test]
+    fn test_plan_layout() {
+        let (m, n) = (30, 17);
+        for axis in [Some(0), Some(1), None] {
+            let (plan, iplan) = match axis {
+                Some(axis) => (
+                    Fft2dPlan::new((m, n), axis, FftDirection::Forward),
+                    Fft2dPlan::new((m, n), axis, FftDirection::Inverse),
+                ),
+                None => (
+                    Fft2dPlan::full((m, n), FftDirection::Forward),
+                    Fft2dPlan::full((m, n), FftDirection::Inverse),
+                ),
+            };
+            let mut plan = plan.layout(Layout::Transposed);
+            let mut iplan = iplan.layout(Layout::Transposed);
+            let mut v = test_vec(m * n);
+            let layout = plan.process(&mut v);
+            let expected = if axis == Some(1) {
+                Layout::Natural
+            } else {
+                Layout::Transposed
+            };
+            assert!(layout == expected);
+            assert!(iplan.process(&mut v) == Layout::Natural);
+            assert!(approx_eq(&v, &test_vec(m * n)));
+        }
+    }
+
+    #[test]
+    fn test_plan_low_memory() {
+        let (m, n) = (64, 30);
+        let work_len = 16;
+        for axis in [Some(0), Some(1), None] {
+            let (plan, iplan) = match axis {
+                Some(axis) => (
+                    Fft2dPlan::new((m, n), axis, FftDirection::Forward),
+                    Fft2dPlan::new((m, n), axis, FftDirection::Inverse),
+                ),
+                None => (
+                    Fft2dPlan::full((m, n), FftDirection::Forward),
+                    Fft2dPlan::full((m, n), FftDirection::Inverse),
+                ),
+            };
+            let norm = Normalization::Ortho;
+            let mut expected = test_vec(m * n);
+            let mut plan = plan.normalization(norm);
+            plan.process(&mut expected);
+
+            let mut plan = plan.low_memory(work_len);
+            let mut iplan = iplan.normalization(norm).low_memory(work_len);
+            assert!(plan.peak_memory() < m * n * std::mem::size_of::<Complex<f64>>());
+            let mut v = test_vec(m * n);
+            assert!(plan.process(&mut v) == Layout::Natural);
+            assert!(approx_eq(&v, &expected));
+            iplan.process(&mut v);
+            assert!(approx_eq(&v, &test_vec(m * n)));
+        }
+    }
+
+    #[test]
+    fn test_plan_parallel() {
+        let (m, n) = (64, 30);
+        for axis in [Some(0), Some(1), None] {
+            let (plan, iplan) = match axis {
+                Some(axis) => (
+                    Fft2dPlan::new((m, n), axis, FftDirection::Forward),
+                    Fft2dPlan::new((m, n), axis, FftDirection::Inverse),
+                ),
+                None => (
+                    Fft2dPlan::full((m, n), FftDirection::Forward),
+                    Fft2dPlan::full((m, n), FftDirection::Inverse),
+                ),
+            };
+            let mut expected = test_vec(m * n);
+            let mut plan = plan.normalization(Normalization::Forward);
+            plan.process(&mut expected);
+
+            let mut plan = plan.parallel(true);
+            let mut iplan = iplan.normalization(Normalization::Forward).parallel(true);
+            let mut v = test_vec(m * n);
+            plan.process(&mut v);
+            assert!(approx_eq(&v, &expected));
+            iplan.process(&mut v);
+            assert!(approx_eq(&v, &test_vec(m * n)));
+        }
+    }
+
+    #[test]
+    fn test_plan_parallel_peak_memory() {
+        let (m, n) = (509, 3);
+        let plan = Fft2dPlan::<f64>::new((m, n), 0, FftDirection::Forward).parallel(true);
+        let thread_len = FftPlanner::<f64>::new()
+            .plan_fft_forward(m)
+            .get_inplace_scratch_len();
+        let len = m * n + rayon::current_num_threads() * thread_len;
+        let expected = len * std::mem::size_of::<Complex<f64>>();
+        assert!(
+            plan.peak_memory() == expected,
+            "{} != {}",
+            plan.peak_memory(),
+            expected
+        );
+    }
+}
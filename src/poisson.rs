@@ -0,0 +1,199 @@
+//! Spectral Poisson and Helmholtz solvers on doubly periodic 2D grids
+//!
+//! Solves
+//!
+//! (∇² − λ) u = f
+//!
+//! on an m x n grid of a lx x ly periodic domain, with λ = 0 for the Poisson
+//! equation. Both axes are transformed with [`Fft2dPlan`], the spectrum is
+//! divided by the symbol −(k0² + k1²) − λ in transposed layout and
+//! transformed back.
+//!
+//! Modes where the symbol vanishes are undetermined and set to zero. For the
+//! Poisson equation this is the zero mode, i.e. the mean of the solution,
+//! which is set by the [`Gauge`].
+//!
+//! # Example
+//! ```
+//! use ndfft_test::poisson::PoissonSolver;
+//! use std::f64::consts::PI;
+//!
+//! let (m, n) = (16, 8);
+//! let rhs = vec![0.; m * n];
+//! let mut u = vec![0.; m * n];
+//! let mut solver: PoissonSolver<f64> = PoissonSolver::new((m, n), (2. * PI, 2. * PI));
+//! solver.solve(&rhs, &mut u);
+//! ```
+use crate::freq::{wavenumbers, Spectrum};
+use crate::ndfft_with_transpose::{to_float, Layout};
+use crate::plan::Fft2dPlan;
+use rustfft::{num_complex::Complex, num_traits::Zero, FftDirection, FftNum};
+
+/// Treatment of the undetermined modes of the solution
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Gauge {
+    /// Solution with zero mean, the mean of the right-hand side is ignored
+    #[default]
+    ZeroMean,
+    /// Solution with the given mean, the mean of the right-hand side is ignored
+    Mean(f64),
+}
+
+/// Reusable solver of (∇² − λ) u = f on a periodic m x n grid
+///
+/// Generic over the float type of the data, `f64` by default.
+pub struct PoissonSolver<T: FftNum = f64> {
+    m: usize,
+    n: usize,
+    gauge: Gauge,
+    forward: Fft2dPlan<T>,
+    inverse: Fft2dPlan<T>,
+    /// Inverse of the symbol in transposed layout, zero where it vanishes
+    inv_symbol: Vec<T>,
+    buffer: Vec<Complex<T>>,
+}
+
+impl<T: FftNum> PoissonSolver<T> {
+    /// Plan the Poisson equation ∇²u = f on a grid of size `shape` with
+    /// periods `lengths`
+    pub fn new(shape: (usize, usize), lengths: (f64, f64)) -> Self {
+        Self::helmholtz(shape, lengths, 0.)
+    }
+
+    /// Plan the Helmholtz equation (∇² − λ) u = f on a grid of size `shape`
+    /// with periods `lengths`
+    ///
+    /// For λ > 0 all modes are determined and the gauge has no effect.
+    pub fn helmholtz(shape: (usize, usize), lengths: (f64, f64), lambda: f64) -> Self {
+        let (m, n) = shape;
+        let k0 = wavenumbers(m, lengths.0, Spectrum::Full);
+        let k1 = wavenumbers(n, lengths.1, Spectrum::Full);
+        // Transposed spectrum, i.e. n x m
+        let inv_symbol = k1
+            .iter()
+            .flat_map(|k1| {
+                k0.iter().map(move |k0| {
+                    let symbol = -(k0 * k0 + k1 * k1) - lambda;
+                    if symbol.abs() < f64::EPSILON * (1. + lambda.abs()) {
+                        T::zero()
+                    } else {
+                        to_float(1. / symbol)
+                    }
+                })
+            })
+            .collect();
+        Self {
+            m,
+            n,
+            gauge: Gauge::default(),
+            forward: Fft2dPlan::full(shape, FftDirection::Forward).layout(Layout::Transposed),
+            inverse: Fft2dPlan::full(shape, FftDirection::Inverse).layout(Layout::Transposed),
+            inv_symbol,
+            buffer: vec![Complex::zero(); m * n],
+        }
+    }
+
+    /// Set the gauge, default is [`Gauge::ZeroMean`]
+    #[must_use]
+    pub fn gauge(mut self, gauge: Gauge) -> Self {
+        self.gauge = gauge;
+        self
+    }
+
+    /// Shape (m, n) of the grid
+    pub fn shape(&self) -> (usize, usize) {
+        (self.m, self.n)
+    }
+
+    /// Solve for the right-hand side `rhs`, the solution is written to `u`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rhs` or `u` do not have m * n elements.
+    pub fn solve(&mut self, rhs: &[T], u: &mut [T]) {
+        let size = self.m * self.n;
+        assert!(rhs.len() == size, "{} != {}", rhs.len(), size);
+        assert!(u.len() == size, "{} != {}", u.len(), size);
+        for (y, x) in self.buffer.iter_mut().zip(rhs.iter()) {
+            *y = Complex::new(*x, T::zero());
+        }
+        self.forward.process(&mut self.buffer);
+        for (x, s) in self.buffer.iter_mut().zip(self.inv_symbol.iter()) {
+            *x = *x * *s;
+        }
+        // The zero mode is the first element in both layouts
+        if let (Gauge::Mean(mean), Some(x)) = (self.gauge, self.buffer.first_mut()) {
+            if self.inv_symbol[0].is_zero() {
+                *x = Complex::new(to_float(mean * size as f64), T::zero());
+            }
+        }
+        self.inverse.process(&mut self.buffer);
+        for (y, x) in u.iter_mut().zip(self.buffer.iter()) {
+            *y = x.re;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_array::grid;
+    use std::f64::consts::PI;
+
+    fn assert_approx_eq(result: &[f64], expected: &[f64], tol: f64) {
+        assert!(result.len() == expected.len());
+        for (a, b) in result.iter().zip(expected.iter()) {
+            assert!((a - b).abs() < tol, "{} != {}", a, b);
+        }
+    }
+
+    #[test]
+    fn test_poisson() {
+        let (m, n) = (32, 24);
+        let (lx, ly) = (2. * PI, 3.);
+        let (a, b) = (2., 2. * PI / ly);
+        let exact = grid(m, n, lx, ly, |x, y| (a * x).sin() * (b * y).cos());
+        // Constant offset of the rhs is ignored
+        let rhs: Vec<f64> = exact.iter().map(|u| -(a * a + b * b) * u + 1.).collect();
+        let mut u = vec![0.; m * n];
+
+        let mut solver: PoissonSolver<f64> = PoissonSolver::new((m, n), (lx, ly));
+        solver.solve(&rhs, &mut u);
+        assert_approx_eq(&u, &exact, 1e-10);
+
+        let mut solver: PoissonSolver<f64> =
+            PoissonSolver::new((m, n), (lx, ly)).gauge(Gauge::Mean(2.5));
+        solver.solve(&rhs, &mut u);
+        let expected: Vec<f64> = exact.iter().map(|u| u + 2.5).collect();
+        assert_approx_eq(&u, &expected, 1e-10);
+    }
+
+    #[test]
+    fn test_helmholtz() {
+        let (m, n) = (20, 16);
+        let (lx, ly) = (2. * PI, 2. * PI);
+        let lambda = 2.;
+        let exact = grid(m, n, lx, ly, |x, y| (3. * x).cos() * (2. * y).sin() + 0.5);
+        let rhs: Vec<f64> = exact
+            .iter()
+            .map(|u| -(9. + 4.) * (u - 0.5) - lambda * u)
+            .collect();
+        let mut u = vec![0.; m * n];
+        let mut solver: PoissonSolver<f64> = PoissonSolver::helmholtz((m, n), (lx, ly), lambda);
+        solver.solve(&rhs, &mut u);
+        assert_approx_eq(&u, &exact, 1e-10);
+    }
+
+    #[test]
+    fn test_poisson_f32() {
+        let (m, n) = (16, 12);
+        let (lx, ly) = (2. * PI, 2. * PI);
+        let exact = grid(m, n, lx, ly, |x, y| x.sin() * (2. * y).sin());
+        let rhs: Vec<f32> = exact.iter().map(|u| (-5. * u) as f32).collect();
+        let mut u = vec![0_f32; m * n];
+        let mut solver: PoissonSolver<f32> = PoissonSolver::new((m, n), (lx, ly));
+        solver.solve(&rhs, &mut u);
+        let u: Vec<f64> = u.iter().map(|x| f64::from(*x)).collect();
+        assert_approx_eq(&u, &exact, 1e-5);
+    }
+}
@@ -0,0 +1,451 @@
+//! Periodograms and radially or azimuthally averaged power spectra
+//!
+//! [`Periodogram`] computes the power |û(k)|² of real m x n fields with
+//! [`rfft2d_with_transpose`], optionally tapered by a separable window and
+//! averaged over a batch of fields (Welch's method). The power is normalized
+//! such that its sum over all modes of the full spectrum equals the mean
+//! square of the (windowed and rescaled) field.
+//!
+//! Real fields yield the Hermitian half-spectrum of m x (n / 2 + 1) modes.
+//! Every column except the first and, for even n, the Nyquist column stands
+//! for itself and its complex conjugate, which is accounted for by
+//! [`hermitian_weights`] in all sums, e.g. in [`radial_spectrum`] and
+//! [`azimuthal_spectrum`].
+//!
+//! # Example
+//! ```
+//! use ndfft_test::freq::Spectrum;
+//! use ndfft_test::power_spectrum::{linear_edges, radial_spectrum, Periodogram};
+//! use std::f64::consts::PI;
+//!
+//! let (m, n) = (32, 32);
+//! let field = vec![1.; m * n];
+//! let mut periodogram = Periodogram::new((m, n));
+//! let mut power = vec![0.; periodogram.spectrum_len()];
+//! periodogram.process(&field, &mut power);
+//! // Shells of unit width around integer wavenumbers
+//! let edges = linear_edges(-0.5, 16.5, 17);
+//! let shells = radial_spectrum(&power, m, n, (2. * PI, 2. * PI), (Spectrum::Full, Spectrum::Half), &edges);
+//! assert!((shells.sum[0] - 1.).abs() < 1e-12);
+//! ```
+use crate::freq::{wavenumber_mesh2d, Spectrum};
+use crate::ndfft_with_transpose::{rfft2d_with_transpose, Layout, Normalization};
+use realfft::{RealFftPlanner, RealToComplex};
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+/// Reusable periodogram of real fields on an m x n grid
+pub struct Periodogram {
+    m: usize,
+    n: usize,
+    /// Separable window along axis 0 and axis 1, `None` if rectangular
+    window: Option<(Vec<f64>, Vec<f64>)>,
+    r2c: Arc<dyn RealToComplex<f64>>,
+    fft_m: Arc<dyn Fft<f64>>,
+    field: Vec<f64>,
+    spectrum: Vec<Complex<f64>>,
+    scratch: Vec<Complex<f64>>,
+}
+
+impl Periodogram {
+    /// Plan periodograms of fields of size `shape`
+    pub fn new(shape: (usize, usize)) -> Self {
+        let (m, n) = shape;
+        let h = n / 2 + 1;
+        let r2c = RealFftPlanner::<f64>::new().plan_fft_forward(n);
+        let fft_m = FftPlanner::<f64>::new().plan_fft_forward(m);
+        let scratch_len = (m * h + fft_m.get_inplace_scratch_len()).max(r2c.get_scratch_len());
+        Self {
+            m,
+            n,
+            window: None,
+            r2c,
+            fft_m,
+            field: vec![0.; m * n],
+            spectrum: vec![Complex::default(); m * h],
+            scratch: vec![Complex::default(); scratch_len],
+        }
+    }
+
+    /// Taper the fields by the separable window `w0[i] * w1[j]`, e.g. from
+    /// [`Window::periodic`](crate::window::Window::periodic)
+    ///
+    /// The power is rescaled by the mean square of the window, which
+    /// compensates the loss of power of the tapering.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `w0` does not have m elements or `w1` does not have n
+    /// elements.
+    #[must_use]
+    pub fn window(mut self, w0: Vec<f64>, w1: Vec<f64>) -> Self {
+        assert!(w0.len() == self.m, "{} != {}", w0.len(), self.m);
+        assert!(w1.len() == self.n, "{} != {}", w1.len(), self.n);
+        self.window = Some((w0, w1));
+        self
+    }
+
+    /// Shape (m, n) of the fields
+    pub fn shape(&self) -> (usize, usize) {
+        (self.m, self.n)
+    }
+
+    /// Number of modes of the Hermitian half-spectrum, m * (n / 2 + 1)
+    pub fn spectrum_len(&self) -> usize {
+        self.m * (self.n / 2 + 1)
+    }
+
+    /// Power of the half-spectrum of `field`, written to `power`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `field` does not have m * n or `power` does not have
+    /// m * (n / 2 + 1) elements.
+    pub fn process(&mut self, field: &[f64], power: &mut [f64]) {
+        power.fill(0.);
+        self.accumulate(field, power, 1.);
+    }
+
+    /// Welch estimate of the power, i.e. the mean of the periodograms of a
+    /// batch of fields
+    ///
+    /// # Arguments
+    ///
+    /// * `fields` - Batch of k fields, flattened to k * m * n elements
+    /// * `power` - Mean power of the half-spectra, m * (n / 2 + 1) elements
+    ///
+    /// # Panics
+    ///
+    /// Panics if `fields` is empty or not a multiple of m * n, or if `power`
+    /// does not have m * (n / 2 + 1) elements.
+    pub fn welch(&mut self, fields: &[f64], power: &mut [f64]) {
+        let size = self.m * self.n;
+        assert!(
+            !fields.is_empty() && fields.len().is_multiple_of(size.max(1)),
+            "{} is not a multiple of {}",
+            fields.len(),
+            size
+        );
+        let k = fields.len() / size.max(1);
+        power.fill(0.);
+        for field in fields.chunks_exact(size) {
+            self.accumulate(field, power, 1. / k as f64);
+        }
+    }
+
+    /// Add `weight` times the power of `field` to `power`
+    fn accumulate(&mut self, field: &[f64], power: &mut [f64], weight: f64) {
+        let (m, n) = (self.m, self.n);
+        assert!(field.len() == m * n, "{} != {}", field.len(), m * n);
+        assert!(
+            power.len() == self.spectrum_len(),
+            "{} != {}",
+            power.len(),
+            self.spectrum_len()
+        );
+        // The window is applied while copying the input, which is
+        // overwritten by the transform otherwise
+        let energy = match &self.window {
+            Some((w0, w1)) => {
+                for ((src, dst), a) in field
+                    .chunks_exact(n)
+                    .zip(self.field.chunks_exact_mut(n))
+                    .zip(w0)
+                {
+                    for ((x, y), b) in src.iter().zip(dst.iter_mut()).zip(w1) {
+                        *y = x * a * b;
+                    }
+                }
+                w0.iter().map(|a| a * a).sum::<f64>() * w1.iter().map(|b| b * b).sum::<f64>()
+            }
+            None => {
+                self.field.copy_from_slice(field);
+                (m * n) as f64
+            }
+        };
+        rfft2d_with_transpose(
+            &mut self.field,
+            &mut self.spectrum,
+            &mut self.scratch,
+            m,
+            n,
+            &self.r2c,
+            &self.fft_m,
+            Normalization::Backward,
+        );
+        let scale = weight / ((m * n) as f64 * energy);
+        for (p, x) in power.iter_mut().zip(self.spectrum.iter()) {
+            *p += x.norm_sqr() * scale;
+        }
+    }
+}
+
+/// Multiplicity of the columns of a Hermitian half-spectrum of a real field
+/// with `n` points along axis 1
+///
+/// 1 for the zero column and, for even n, the Nyquist column, which have no
+/// conjugate partner in the half-spectrum, 2 for all other columns.
+pub fn hermitian_weights(n: usize) -> Vec<f64> {
+    (0..=n / 2)
+        .map(|j| {
+            if j == 0 || (n.is_multiple_of(2) && j == n / 2) {
+                1.
+            } else {
+                2.
+            }
+        })
+        .collect()
+}
+
+/// `count` bins of equal width between `start` and `stop`, i.e. `count` + 1
+/// edges
+pub fn linear_edges(start: f64, stop: f64, count: usize) -> Vec<f64> {
+    let width = (stop - start) / count.max(1) as f64;
+    (0..=count).map(|i| start + i as f64 * width).collect()
+}
+
+/// Power binned over shells of |k| or sectors of the angle of k
+#[derive(Debug, Clone, PartialEq)]
+pub struct BinnedSpectrum {
+    /// Bin edges, bin i holds edges\[i\] <= x < edges\[i + 1\]
+    pub edges: Vec<f64>,
+    /// Total power of the modes in each bin, e.g. the energy spectrum E(k)
+    pub sum: Vec<f64>,
+    /// Number of modes in each bin, counting conjugate partners of a
+    /// half-spectrum
+    pub count: Vec<f64>,
+}
+
+impl BinnedSpectrum {
+    /// Mean power of the modes in each bin, zero for empty bins
+    pub fn mean(&self) -> Vec<f64> {
+        self.sum
+            .iter()
+            .zip(self.count.iter())
+            .map(|(s, c)| if *c > 0. { s / c } else { 0. })
+            .collect()
+    }
+
+    /// Centers of the bins
+    pub fn centers(&self) -> Vec<f64> {
+        self.edges.windows(2).map(|e| 0.5 * (e[0] + e[1])).collect()
+    }
+}
+
+/// Power binned over shells of the wavenumber magnitude |k|
+///
+/// # Arguments
+///
+/// * `power` - Power of an m x n field, e.g. from [`Periodogram`]
+/// * `m` - Number of points along axis 0
+/// * `n` - Number of points along axis 1
+/// * `lengths` - Periods of the domain along both axes
+/// * `spectrum` - Ordering of the power along both axes, [`Spectrum::Half`]
+///   along axis 1 for the half-spectrum of a real field
+/// * `edges` - Increasing bin edges of |k|, modes outside are ignored
+///
+/// # Panics
+///
+/// Panics if `power` does not match the spectrum.
+pub fn radial_spectrum(
+    power: &[f64],
+    m: usize,
+    n: usize,
+    lengths: (f64, f64),
+    spectrum: (Spectrum, Spectrum),
+    edges: &[f64],
+) -> BinnedSpectrum {
+    binned_spectrum(power, m, n, lengths, spectrum, edges, |k0, k1| k0.hypot(k1))
+}
+
+/// Power binned over sectors of the angle of k
+///
+/// The angle atan2(k0, k1) is taken modulo pi in [0, pi), since the power of
+/// a real field is symmetric under k -> -k.
+///
+/// # Arguments
+///
+/// * `power` - Power of an m x n field, e.g. from [`Periodogram`]
+/// * `m` - Number of points along axis 0
+/// * `n` - Number of points along axis 1
+/// * `lengths` - Periods of the domain along both axes
+/// * `spectrum` - Ordering of the power along both axes, [`Spectrum::Half`]
+///   along axis 1 for the half-spectrum of a real field
+/// * `edges` - Increasing bin edges of the angle, modes outside and the zero
+///   mode are ignored
+///
+/// # Panics
+///
+/// Panics if `power` does not match the spectrum.
+pub fn azimuthal_spectrum(
+    power: &[f64],
+    m: usize,
+    n: usize,
+    lengths: (f64, f64),
+    spectrum: (Spectrum, Spectrum),
+    edges: &[f64],
+) -> BinnedSpectrum {
+    binned_spectrum(power, m, n, lengths, spectrum, edges, |k0, k1| {
+        if k0 == 0. && k1 == 0. {
+            f64::NAN
+        } else {
+            k0.atan2(k1).rem_euclid(PI)
+        }
+    })
+}
+
+/// Bin `power` by `coordinate(k0, k1)`, NaN coordinates are ignored
+fn binned_spectrum<F: Fn(f64, f64) -> f64>(
+    power: &[f64],
+    m: usize,
+    n: usize,
+    lengths: (f64, f64),
+    spectrum: (Spectrum, Spectrum),
+    edges: &[f64],
+    coordinate: F,
+) -> BinnedSpectrum {
+    let (rows, cols) = (spectrum.0.len(m), spectrum.1.len(n));
+    assert!(
+        power.len() == rows * cols,
+        "{} != {}",
+        power.len(),
+        rows * cols
+    );
+    let (k0, k1) = wavenumber_mesh2d(m, n, lengths, spectrum, Layout::Natural);
+    let weights = match spectrum.1 {
+        Spectrum::Half => hermitian_weights(n),
+        _ => vec![1.; cols],
+    };
+    let bins = edges.len().max(1) - 1;
+    let mut sum = vec![0.; bins];
+    let mut count = vec![0.; bins];
+    for (i, p) in power.iter().enumerate() {
+        let x = coordinate(k0[i], k1[i]);
+        // Index of the first edge greater than x
+        let upper = edges.partition_point(|e| *e <= x);
+        if upper > 0 && upper <= bins && !x.is_nan() {
+            let w = weights[i % cols];
+            sum[upper - 1] += w * p;
+            count[upper - 1] += w;
+        }
+    }
+    BinnedSpectrum {
+        edges: edges.to_vec(),
+        sum,
+        count,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_array::grid;
+
+    #[test]
+    fn test_periodogram_parseval() {
+        for (m, n) in [(16, 12), (9, 7)] {
+            let field = grid(m, n, 2. * PI, 2. * PI, |x, y| {
+                (x + 2. * y).sin() + 0.3 * (x * y).cos()
+            });
+            let mut periodogram = Periodogram::new((m, n));
+            let mut power = vec![0.; periodogram.spectrum_len()];
+            periodogram.process(&field, &mut power);
+            let weights = hermitian_weights(n);
+            let total: f64 = power
+                .iter()
+                .enumerate()
+                .map(|(i, p)| weights[i % (n / 2 + 1)] * p)
+                .sum();
+            let mean_square = field.iter().map(|x| x * x).sum::<f64>() / (m * n) as f64;
+            assert!((total - mean_square).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_radial_spectrum() {
+        let (m, n) = (16, 16);
+        let lengths = (2. * PI, 2. * PI);
+        let field = grid(m, n, 2. * PI, 2. * PI, |x, y| {
+            2. * (3. * x).cos() + (4. * y).sin() + 1.
+        });
+        let mut periodogram = Periodogram::new((m, n));
+        let mut power = vec![0.; periodogram.spectrum_len()];
+        periodogram.process(&field, &mut power);
+        let edges = linear_edges(-0.5, 8.5, 9);
+        let spectrum = (Spectrum::Full, Spectrum::Half);
+        let shells = radial_spectrum(&power, m, n, lengths, spectrum, &edges);
+        // Mean square of each component
+        let expected = [1., 0., 0., 2., 0.5, 0., 0., 0., 0.];
+        for (result, expected) in shells.sum.iter().zip(expected) {
+            assert!(
+                (result - expected).abs() < 1e-12,
+                "{} != {}",
+                result,
+                expected
+            );
+        }
+        // Shell 0.5 <= |k| < 1.5 holds the modes (±1, 0), (0, ±1) and (±1, ±1)
+        assert!((shells.count[1] - 8.).abs() < 1e-12);
+
+        // Same result from the full spectrum
+        let mut full = vec![0.; m * n];
+        let h = n / 2 + 1;
+        for i in 0..m {
+            for j in 0..n {
+                full[i * n + j] = if j < h {
+                    power[i * h + j]
+                } else {
+                    power[((m - i) % m) * h + n - j]
+                };
+            }
+        }
+        let spectrum = (Spectrum::Full, Spectrum::Full);
+        let full_shells = radial_spectrum(&full, m, n, lengths, spectrum, &edges);
+        for (a, b) in full_shells.sum.iter().zip(shells.sum.iter()) {
+            assert!((a - b).abs() < 1e-12);
+        }
+        assert!(full_shells.count == shells.count);
+    }
+
+    #[test]
+    fn test_azimuthal_spectrum() {
+        let (m, n) = (16, 12);
+        let lengths = (2. * PI, 2. * PI);
+        // Variation along axis 0 only, i.e. k1 = 0 and angle pi / 2
+        let field = grid(m, n, 2. * PI, 2. * PI, |x, _| (2. * x).cos());
+        let mut periodogram = Periodogram::new((m, n));
+        let mut power = vec![0.; periodogram.spectrum_len()];
+        periodogram.process(&field, &mut power);
+        let edges = linear_edges(0., PI, 4);
+        let spectrum = (Spectrum::Full, Spectrum::Half);
+        let sectors = azimuthal_spectrum(&power, m, n, lengths, spectrum, &edges);
+        let expected = [0., 0., 0.5, 0.];
+        for (result, expected) in sectors.sum.iter().zip(expected) {
+            assert!(
+                (result - expected).abs() < 1e-12,
+                "{} != {}",
+                result,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_welch() {
+        let (m, n) = (8, 10);
+        let a = grid(m, n, 2. * PI, 2. * PI, |x, y| x.sin() * y.cos());
+        let b = grid(m, n, 2. * PI, 2. * PI, |x, y| (2. * x + y).cos());
+        let w0: Vec<f64> = grid(m, 1, 2. * PI, 2. * PI, |x, _| 0.5 - 0.5 * x.cos());
+        let w1 = vec![1.; n];
+        let mut periodogram = Periodogram::new((m, n)).window(w0, w1);
+        let len = periodogram.spectrum_len();
+        let (mut pa, mut pb, mut mean) = (vec![0.; len], vec![0.; len], vec![0.; len]);
+        periodogram.process(&a, &mut pa);
+        periodogram.process(&b, &mut pb);
+        periodogram.welch(&[a, b].concat(), &mut mean);
+        for ((x, y), z) in pa.iter().zip(pb.iter()).zip(mean.iter()) {
+            assert!((0.5 * (x + y) - z).abs() < 1e-12);
+        }
+    }
+}
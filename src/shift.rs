@@ -0,0 +1,220 @@
+//! Numpy-style `fftshift` and `ifftshift` of flattened row-major arrays
+//!
+//! `fftshift` moves the zero-frequency mode to the center, i.e. it rolls each
+//! axis of length n by n / 2. `ifftshift` is its inverse and rolls by
+//! -(n / 2), which differs from `fftshift` for odd n.
+//!
+//! The in-place versions rotate contiguous blocks with [`slice::rotate_right`],
+//! the out-of-place versions copy contiguous rows to their shifted position.
+use ndarray::{Array, Dimension};
+
+/// In-place fftshift along all axes of a flattened array of size `shape`
+///
+/// # Panics
+///
+/// Panics if `v` does not match `shape`.
+pub fn fftshift<T: Copy>(v: &mut [T], shape: &[usize]) {
+    for axis in 0..shape.len() {
+        fftshift_axis(v, shape, axis);
+    }
+}
+
+/// In-place ifftshift along all axes of a flattened array of size `shape`
+///
+/// # Panics
+///
+/// Panics if `v` does not match `shape`.
+pub fn ifftshift<T: Copy>(v: &mut [T], shape: &[usize]) {
+    for axis in 0..shape.len() {
+        ifftshift_axis(v, shape, axis);
+    }
+}
+
+/// In-place fftshift along `axis` of a flattened array of size `shape`
+///
+/// # Panics
+///
+/// Panics if `v` does not match `shape` or `axis` is out of bounds.
+pub fn fftshift_axis<T: Copy>(v: &mut [T], shape: &[usize], axis: usize) {
+    roll_axis(v, shape, axis, shape[axis] / 2);
+}
+
+/// In-place ifftshift along `axis` of a flattened array of size `shape`
+///
+/// # Panics
+///
+/// Panics if `v` does not match `shape` or `axis` is out of bounds.
+pub fn ifftshift_axis<T: Copy>(v: &mut [T], shape: &[usize], axis: usize) {
+    let len = shape[axis];
+    roll_axis(v, shape, axis, len - len / 2);
+}
+
+/// In-place fftshift along both axes of a flattened m x n array
+pub fn fftshift2d<T: Copy>(v: &mut [T], m: usize, n: usize) {
+    fftshift(v, &[m, n]);
+}
+
+/// In-place ifftshift along both axes of a flattened m x n array
+pub fn ifftshift2d<T: Copy>(v: &mut [T], m: usize, n: usize) {
+    ifftshift(v, &[m, n]);
+}
+
+/// Out-of-place fftshift along all axes of a flattened array of size `shape`
+///
+/// # Panics
+///
+/// Panics if `src` or `dst` do not match `shape`.
+pub fn fftshift_to<T: Copy>(src: &[T], dst: &mut [T], shape: &[usize]) {
+    let shifts: Vec<usize> = shape.iter().map(|len| len / 2).collect();
+    roll_to(src, dst, shape, &shifts);
+}
+
+/// Out-of-place ifftshift along all axes of a flattened array of size `shape`
+///
+/// # Panics
+///
+/// Panics if `src` or `dst` do not match `shape`.
+pub fn ifftshift_to<T: Copy>(src: &[T], dst: &mut [T], shape: &[usize]) {
+    let shifts: Vec<usize> = shape.iter().map(|len| len - len / 2).collect();
+    roll_to(src, dst, shape, &shifts);
+}
+
+/// In-place fftshift along all axes of an ndarray
+///
+/// Arrays which are not in standard layout are shifted in a temporary copy.
+pub fn fftshift_array<T: Copy, D: Dimension>(a: &mut Array<T, D>) {
+    shift_array(a, fftshift);
+}
+
+/// In-place ifftshift along all axes of an ndarray
+///
+/// Arrays which are not in standard layout are shifted in a temporary copy.
+pub fn ifftshift_array<T: Copy, D: Dimension>(a: &mut Array<T, D>) {
+    shift_array(a, ifftshift);
+}
+
+fn shift_array<T: Copy, D: Dimension, F: Fn(&mut [T], &[usize])>(a: &mut Array<T, D>, shift: F) {
+    let shape = a.shape().to_vec();
+    if let Some(v) = a.as_slice_mut() {
+        shift(v, &shape);
+    } else {
+        let mut tmp = a.as_standard_layout().into_owned();
+        shift(tmp.as_slice_mut().unwrap(), &shape);
+        a.assign(&tmp);
+    }
+}
+
+/// Roll `axis` by `shift`, i.e. element i moves to (i + shift) % len
+fn roll_axis<T: Copy>(v: &mut [T], shape: &[usize], axis: usize, shift: usize) {
+    let size: usize = shape.iter().product();
+    assert!(v.len() == size, "{} != {}", v.len(), size);
+    assert!(
+        axis < shape.len(),
+        "axis {} out of bounds for {} dimensions",
+        axis,
+        shape.len()
+    );
+    let inner: usize = shape[axis + 1..].iter().product();
+    let block = shape[axis] * inner;
+    if block == 0 {
+        return;
+    }
+    for x in v.chunks_exact_mut(block) {
+        x.rotate_right(shift * inner);
+    }
+}
+
+/// Out-of-place roll of every axis by its `shifts`
+fn roll_to<T: Copy>(src: &[T], dst: &mut [T], shape: &[usize], shifts: &[usize]) {
+    let size: usize = shape.iter().product();
+    assert!(src.len() == size, "{} != {}", src.len(), size);
+    assert!(dst.len() == size, "{} != {}", dst.len(), size);
+    if size == 0 {
+        return;
+    }
+    let len = shape[0];
+    let shift = shifts[0];
+    if shape.len() == 1 {
+        dst[shift..].copy_from_slice(&src[..len - shift]);
+        dst[..shift].copy_from_slice(&src[len - shift..]);
+        return;
+    }
+    let inner = size / len;
+    for (i, x) in src.chunks_exact(inner).enumerate() {
+        let j = (i + shift) % len;
+        roll_to(x, &mut dst[j * inner..][..inner], &shape[1..], &shifts[1..]);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ndarray::Array2;
+
+    #[test]
+    fn test_fftshift_1d() {
+        // numpy.fft.fftshift / ifftshift
+        let mut v = [0, 1, 2, 3, 4];
+        fftshift(&mut v, &[5]);
+        assert!(v == [3, 4, 0, 1, 2]);
+        let mut v = [0, 1, 2, 3, 4];
+        ifftshift(&mut v, &[5]);
+        assert!(v == [2, 3, 4, 0, 1]);
+        let mut v = [0, 1, 2, 3];
+        fftshift(&mut v, &[4]);
+        assert!(v == [2, 3, 0, 1]);
+    }
+
+    #[test]
+    fn test_fftshift_2d() {
+        let (m, n) = (3, 4);
+        let mut v: Vec<usize> = (0..m * n).collect();
+        fftshift2d(&mut v, m, n);
+        assert!(v == [10, 11, 8, 9, 2, 3, 0, 1, 6, 7, 4, 5]);
+        ifftshift2d(&mut v, m, n);
+        assert!(v == (0..m * n).collect::<Vec<usize>>());
+
+        let mut v: Vec<usize> = (0..m * n).collect();
+        fftshift_axis(&mut v, &[m, n], 1);
+        assert!(v == [2, 3, 0, 1, 6, 7, 4, 5, 10, 11, 8, 9]);
+    }
+
+    #[test]
+    fn test_shift_to() {
+        for shape in [vec![7], vec![5, 6], vec![3, 4, 5], vec![4, 1, 3]] {
+            let size: usize = shape.iter().product();
+            let src: Vec<usize> = (0..size).collect();
+            let mut dst = vec![0; size];
+
+            let mut expected = src.clone();
+            fftshift(&mut expected, &shape);
+            fftshift_to(&src, &mut dst, &shape);
+            assert!(dst == expected);
+
+            let mut expected = src.clone();
+            ifftshift(&mut expected, &shape);
+            ifftshift_to(&src, &mut dst, &shape);
+            assert!(dst == expected);
+        }
+    }
+
+    #[test]
+    fn test_shift_array() {
+        let (m, n) = (5, 4);
+        let mut expected: Vec<usize> = (0..m * n).collect();
+        fftshift2d(&mut expected, m, n);
+
+        let mut a = Array2::from_shape_vec((m, n), (0..m * n).collect()).unwrap();
+        fftshift_array(&mut a);
+        assert!(a.as_slice().unwrap() == expected);
+
+        // Fortran order
+        let mut a = Array2::from_shape_vec((n, m), (0..m * n).collect())
+            .unwrap()
+            .reversed_axes();
+        let mut expected = a.as_standard_layout().into_owned();
+        fftshift_array(&mut expected);
+        fftshift_array(&mut a);
+        assert!(a == expected);
+    }
+}
@@ -0,0 +1,419 @@
+//! Selection between the ndrustfft and the transpose backend
+//!
+//! [`Fft2dStrategyPlan`] transforms along one axis of an `Array2` with either
+//!
+//! - [`Strategy::Transpose`]: the transpose method, via [`fft2d_view`]
+//! - [`Strategy::Lanes`]: ndrustfft, which transforms lane by lane and needs
+//!   no work-space of the size of the array
+//!
+//! [`Strategy::Auto`] picks a backend with [`select_strategy`], and optionally
+//! by timing both backends on the host, see [`Fft2dStrategyPlan::calibrate`].
+//!
+//! # Example
+//! ```
+//! use ndarray::Array2;
+//! use ndfft_test::strategy::{Fft2dStrategyPlan, Strategy};
+//! use rustfft::{num_complex::Complex, FftDirection};
+//!
+//! let (m, n) = (64, 32);
+//! let v = Array2::<Complex<f64>>::zeros((m, n));
+//! let mut vhat = Array2::<Complex<f64>>::zeros((m, n));
+//! let mut plan = Fft2dStrategyPlan::new((m, n), 0, FftDirection::Forward, Strategy::Auto)
+//!     .memory_budget(1 << 20)
+//!     .calibrate(3);
+//! plan.process(&v, &mut vhat);
+//! ```
+use crate::ndfft_with_transpose::Normalization;
+use crate::view::{fft2d_view, ifft2d_view, view_scratch_len};
+use crate::wisdom::{Precision, Wisdom, WisdomEntry, WisdomKey};
+use ndarray::Array2;
+use ndrustfft::{ndfft, ndifft, FftHandler};
+use rustfft::{num_complex::Complex, Fft, FftDirection, FftPlanner};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Backend of a 2D fft along one axis
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Strategy {
+    /// Transpose method, all lanes are transformed in a single call
+    Transpose,
+    /// ndrustfft, lanes are copied and transformed one by one
+    Lanes,
+    /// Choose between `Transpose` and `Lanes`
+    #[default]
+    Auto,
+}
+
+/// Timings of both backends measured by [`Fft2dStrategyPlan::calibrate`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Calibration {
+    /// Fastest of the timed runs of the transpose backend
+    pub transpose: Duration,
+    /// Fastest of the timed runs of the lanes backend
+    pub lanes: Duration,
+}
+
+impl Calibration {
+    /// Backend with the shorter timing
+    pub fn fastest(&self) -> Strategy {
+        if self.transpose <= self.lanes {
+            Strategy::Transpose
+        } else {
+            Strategy::Lanes
+        }
+    }
+}
+
+/// Built-in heuristic for [`Strategy::Auto`]
+///
+/// The transpose method is faster along both axes for all measured sizes
+/// (see the benches in the README), so it is chosen unless its work-space
+/// exceeds `memory_budget` (in bytes). Along axis 1 it needs only the
+/// scratch of the fft plan, along axis 0 an additional m x n buffer.
+///
+/// # Arguments
+///
+/// * `shape` - Shape (m, n) of the array
+/// * `axis` - Axis along which the fft is performed
+/// * `memory_budget` - Available work-space in bytes, `None` if unlimited
+///
+/// # Panics
+///
+/// Panics if `axis` is not 0 or 1.
+pub fn select_strategy(
+    shape: (usize, usize),
+    axis: usize,
+    memory_budget: Option<usize>,
+) -> Strategy {
+    assert!(axis < 2, "axis must be 0 or 1, got {}", axis);
+    let len = if axis == 0 { shape.0 } else { shape.1 };
+    let fft = FftPlanner::new().plan_fft_forward(len);
+    if exceeds_budget(shape, axis, &fft, memory_budget) {
+        Strategy::Lanes
+    } else {
+        Strategy::Transpose
+    }
+}
+
+/// Work-space of the transpose backend along `axis` of a standard layout
+/// array, including the scratch of `fft`
+fn workspace_len(shape: (usize, usize), axis: usize, fft: &Arc<dyn Fft<f64>>) -> usize {
+    if axis == 0 {
+        view_scratch_len(shape, fft)
+    } else {
+        fft.get_inplace_scratch_len()
+    }
+}
+
+/// Whether the work-space of the transpose backend exceeds `memory_budget`
+fn exceeds_budget(
+    shape: (usize, usize),
+    axis: usize,
+    fft: &Arc<dyn Fft<f64>>,
+    memory_budget: Option<usize>,
+) -> bool {
+    let bytes = workspace_len(shape, axis, fft) * std::mem::size_of::<Complex<f64>>();
+    memory_budget.is_some_and(|budget| bytes > budget)
+}
+
+/// Plan of a 2D fft along one axis with a selectable backend
+pub struct Fft2dStrategyPlan {
+    m: usize,
+    n: usize,
+    axis: usize,
+    direction: FftDirection,
+    /// Requested strategy, possibly `Auto`
+    requested: Strategy,
+    /// Selected strategy, never `Auto`
+    selected: Strategy,
+    memory_budget: Option<usize>,
+    calibration: Option<Calibration>,
+    fft: Arc<dyn Fft<f64>>,
+    handler: FftHandler<f64>,
+    scratch: Vec<Complex<f64>>,
+}
+
+impl Fft2dStrategyPlan {
+    /// Plan a transform along `axis` of an array of size `shape`
+    ///
+    /// Both directions are normalized like ndrustfft, i.e. with
+    /// [`Normalization::Backward`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `axis` is not 0 or 1.
+    pub fn new(
+        shape: (usize, usize),
+        axis: usize,
+        direction: FftDirection,
+        strategy: Strategy,
+    ) -> Self {
+        assert!(axis < 2, "axis must be 0 or 1, got {}", axis);
+        let len = if axis == 0 { shape.0 } else { shape.1 };
+        let fft = FftPlanner::new().plan_fft(len, direction);
+        let mut plan = Self {
+            m: shape.0,
+            n: shape.1,
+            axis,
+            direction,
+            requested: strategy,
+            selected: Strategy::Transpose,
+            memory_budget: None,
+            calibration: None,
+            fft,
+            handler: FftHandler::new(len),
+            scratch: vec![],
+        };
+        plan.select(strategy);
+        plan
+    }
+
+    /// Limit the work-space of the backend to `bytes`, only affects
+    /// [`Strategy::Auto`]
+    ///
+    /// Discards a previous calibration.
+    #[must_use]
+    pub fn memory_budget(mut self, bytes: usize) -> Self {
+        self.memory_budget = Some(bytes);
+        self.calibration = None;
+        self.select(self.requested);
+        self
+    }
+
+    /// Time both backends over `repeats` runs on the host and select the
+    /// faster one, only affects [`Strategy::Auto`]
+    ///
+    /// The transpose backend is not timed if it exceeds the memory budget.
+    #[must_use]
+    pub fn calibrate(mut self, repeats: usize) -> Self {
+        if self.requested != Strategy::Auto
+            || exceeds_budget(self.shape(), self.axis, &self.fft, self.memory_budget)
+        {
+            return self;
+        }
+        let v = Array2::zeros((self.m, self.n));
+        let mut vhat = Array2::zeros((self.m, self.n));
+        let mut time = |plan: &mut Self, strategy: Strategy| {
+            plan.select(strategy);
+            // Warm-up
+            plan.process(&v, &mut vhat);
+            (0..repeats.max(1))
+                .map(|_| {
+                    let start = Instant::now();
+                    plan.process(&v, &mut vhat);
+                    start.elapsed()
+                })
+                .min()
+                .unwrap()
+        };
+        let calibration = Calibration {
+            lanes: time(&mut self, Strategy::Lanes),
+            transpose: time(&mut self, Strategy::Transpose),
+        };
+        self.select(calibration.fastest());
+        self.calibration = Some(calibration);
+        self
+    }
+
+    /// Like [`Fft2dStrategyPlan::calibrate`], but reuses the entry of
+    /// `wisdom` for this problem if there is one
+    ///
+    /// Otherwise the backends are timed and the outcome is recorded in
+    /// `wisdom`.
+    #[must_use]
+    pub fn calibrate_with_wisdom(mut self, repeats: usize, wisdom: &mut Wisdom) -> Self {
+        if self.requested != Strategy::Auto
+            || exceeds_budget(self.shape(), self.axis, &self.fft, self.memory_budget)
+        {
+            return self;
+        }
+        let key = self.wisdom_key();
+        if let Some(entry) = wisdom.get(&key) {
+            self.select(entry.strategy);
+            self.calibration = Some(entry.calibration);
+            return self;
+        }
+        self = self.calibrate(repeats);
+        if let Some(calibration) = self.calibration {
+            let entry = WisdomEntry {
+                strategy: self.selected,
+                calibration,
+            };
+            wisdom.insert(key, entry);
+        }
+        self
+    }
+
+    /// Key of this problem in a [`Wisdom`] store
+    pub fn wisdom_key(&self) -> WisdomKey {
+        WisdomKey {
+            shape: self.shape(),
+            axis: self.axis,
+            precision: Precision::of::<f64>(),
+            threads: rayon::current_num_threads(),
+        }
+    }
+
+    /// Shape (m, n) of the array
+    pub fn shape(&self) -> (usize, usize) {
+        (self.m, self.n)
+    }
+
+    /// Axis along which the fft is performed
+    pub fn axis(&self) -> usize {
+        self.axis
+    }
+
+    /// Selected backend, never [`Strategy::Auto`]
+    pub fn strategy(&self) -> Strategy {
+        self.selected
+    }
+
+    /// Timings of the last calibration, if any
+    pub fn calibration(&self) -> Option<Calibration> {
+        self.calibration
+    }
+
+    /// Transform `v` along the planned axis and write the result to `vhat`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `v` or `vhat` do not match the planned shape. The lanes
+    /// backend additionally panics if the arrays are not in standard layout.
+    pub fn process(&mut self, v: &Array2<Complex<f64>>, vhat: &mut Array2<Complex<f64>>) {
+        let shape = [self.m, self.n];
+        assert!(v.shape() == shape, "{:?} != {:?}", v.shape(), shape);
+        assert!(vhat.shape() == shape, "{:?} != {:?}", vhat.shape(), shape);
+        let norm = Normalization::Backward;
+        if self.selected != Strategy::Lanes && !vhat.is_standard_layout() {
+            // Other layouts may need the m x n buffer along axis 1, too
+            let len = view_scratch_len(self.shape(), &self.fft);
+            if self.scratch.len() < len {
+                self.scratch.resize(len, Complex::new(0., 0.));
+            }
+        }
+        match (self.selected, self.direction) {
+            (Strategy::Lanes, FftDirection::Forward) => {
+                ndfft(v, vhat, &mut self.handler, self.axis);
+            }
+            (Strategy::Lanes, FftDirection::Inverse) => {
+                ndifft(v, vhat, &mut self.handler, self.axis);
+            }
+            (_, FftDirection::Forward) => {
+                vhat.assign(v);
+                fft2d_view(
+                    vhat.view_mut(),
+                    &mut self.scratch,
+                    &self.fft,
+                    self.axis,
+                    norm,
+                );
+            }
+            (_, FftDirection::Inverse) => {
+                vhat.assign(v);
+                ifft2d_view(
+                    vhat.view_mut(),
+                    &mut self.scratch,
+                    &self.fft,
+                    self.axis,
+                    norm,
+                );
+            }
+        }
+    }
+
+    /// Resolve `strategy` and allocate the work-space of the selected backend
+    fn select(&mut self, strategy: Strategy) {
+        self.selected = match strategy {
+            Strategy::Auto => {
+                if exceeds_budget(self.shape(), self.axis, &self.fft, self.memory_budget) {
+                    Strategy::Lanes
+                } else {
+                    Strategy::Transpose
+                }
+            }
+            strategy => strategy,
+        };
+        let scratch_len = match self.selected {
+            Strategy::Lanes => 0,
+            _ => workspace_len(self.shape(), self.axis, &self.fft),
+        };
+        self.scratch = vec![Complex::new(0., 0.); scratch_len];
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_array::{approx_eq, test_vec};
+
+    #[test]
+    fn test_select_strategy() {
+        let (m, n) = (128, 64);
+        let mut planner = FftPlanner::<f64>::new();
+        assert!(select_strategy((m, n), 0, None) == Strategy::Transpose);
+        assert!(select_strategy((m, n), 1, None) == Strategy::Transpose);
+        // Contiguous lanes along axis 1 only need the scratch of the plan
+        let fft = planner.plan_fft_forward(n);
+        let bytes = fft.get_inplace_scratch_len() * 16;
+        assert!(select_strategy((m, n), 1, Some(bytes)) == Strategy::Transpose);
+        assert!(select_strategy((m, n), 0, Some(bytes)) == Strategy::Lanes);
+        // Work-space along axis 0 is the m x n buffer and the scratch of the plan
+        let fft = planner.plan_fft_forward(m);
+        let bytes = (m * n + fft.get_inplace_scratch_len()) * 16;
+        assert!(select_strategy((m, n), 0, Some(bytes)) == Strategy::Transpose);
+        assert!(select_strategy((m, n), 0, Some(bytes - 1)) == Strategy::Lanes);
+        let plan = Fft2dStrategyPlan::new((m, n), 0, FftDirection::Forward, Strategy::Auto)
+            .memory_budget(1024);
+        assert!(plan.strategy() == Strategy::Lanes);
+        assert!(plan.scratch.is_empty());
+    }
+
+    #[test]
+    fn test_strategy_plan_fortran_layout() {
+        use ndarray::ShapeBuilder;
+        let (m, n) = (12, 10);
+        let v = Array2::from_shape_vec((m, n), test_vec(m * n)).unwrap();
+        let mut expected = Array2::zeros((m, n));
+        let mut vhat = Array2::zeros((m, n).f());
+        Fft2dStrategyPlan::new((m, n), 1, FftDirection::Forward, Strategy::Lanes)
+            .process(&v, &mut expected);
+        Fft2dStrategyPlan::new((m, n), 1, FftDirection::Forward, Strategy::Transpose)
+            .process(&v, &mut vhat);
+        let vhat = vhat.as_standard_layout().into_owned();
+        assert!(approx_eq(
+            vhat.as_slice().unwrap(),
+            expected.as_slice().unwrap()
+        ));
+    }
+
+    #[test]
+    fn test_strategy_plan() {
+        let (m, n) = (24, 20);
+        let v = Array2::from_shape_vec((m, n), test_vec(m * n)).unwrap();
+        for axis in 0..2 {
+            let mut expected = Array2::zeros((m, n));
+            let mut vhat = Array2::zeros((m, n));
+            let mut back = Array2::zeros((m, n));
+            for strategy in [Strategy::Lanes, Strategy::Transpose, Strategy::Auto] {
+                let mut forward =
+                    Fft2dStrategyPlan::new((m, n), axis, FftDirection::Forward, strategy)
+                        .calibrate(2);
+                let mut inverse =
+                    Fft2dStrategyPlan::new((m, n), axis, FftDirection::Inverse, strategy);
+                assert!(forward.strategy() != Strategy::Auto);
+                assert!(forward.calibration().is_some() == (strategy == Strategy::Auto));
+                forward.process(&v, &mut vhat);
+                if strategy == Strategy::Lanes {
+                    expected.assign(&vhat);
+                }
+                assert!(approx_eq(
+                    vhat.as_slice().unwrap(),
+                    expected.as_slice().unwrap()
+                ));
+                inverse.process(&vhat, &mut back);
+                assert!(approx_eq(back.as_slice().unwrap(), v.as_slice().unwrap()));
+            }
+        }
+    }
+}
@@ -8,3 +8,26 @@ pub fn test_array2(n: usize) -> Array2<Complex<f64>> {
 pub fn test_vec(n: usize) -> Vec<Complex<f64>> {
     (0..n).map(|x| Complex::new(x as f64, x as f64)).collect()
 }
+
+/// Evaluate f(x, y) on an m x n grid of a lx x ly domain, flattened in
+/// row-major order
+pub fn grid<F: Fn(f64, f64) -> f64>(m: usize, n: usize, lx: f64, ly: f64, f: F) -> Vec<f64> {
+    (0..m * n)
+        .map(|i| {
+            let x = lx * (i / n) as f64 / m as f64;
+            let y = ly * (i % n) as f64 / n as f64;
+            f(x, y)
+        })
+        .collect()
+}
+
+/// Check if two flattened arrays agree within a tolerance relative
+/// to the largest magnitude in `expected`
+pub fn approx_eq(result: &[Complex<f64>], expected: &[Complex<f64>]) -> bool {
+    let scale = expected.iter().map(|x| x.norm()).fold(1., f64::max);
+    result.len() == expected.len()
+        && result
+            .iter()
+            .zip(expected.iter())
+            .all(|(a, b)| (a - b).norm() < 1e-10 * scale)
+}
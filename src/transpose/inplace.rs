@@ -171,7 +171,9 @@ fn join<T: Copy>(a: &mut [T], q: usize, n: usize) {
 /// transposition (2018)
 fn column_transpose<T: Copy>(a: &mut [T], rows: usize, cols: usize, w: &mut [T], iw: usize) {
     if rows * cols <= iw {
-        oop_transpose(a, &mut w[..rows * cols], rows, cols);
+        let w = &mut w[..rows * cols];
+        oop_transpose(a, w, cols, rows);
+        a[..rows * cols].copy_from_slice(w);
     } else {
         let q = rows / cols;
         let r = rows % cols;
@@ -197,7 +199,9 @@ fn column_transpose<T: Copy>(a: &mut [T], rows: usize, cols: usize, w: &mut [T],
 /// transposition (2018)
 fn row_transpose<T: Copy>(a: &mut [T], rows: usize, cols: usize, w: &mut [T], iw: usize) {
     if rows * cols <= iw {
-        oop_transpose(a, &mut w[..rows * cols], rows, cols);
+        let w = &mut w[..rows * cols];
+        oop_transpose(a, w, cols, rows);
+        a[..rows * cols].copy_from_slice(w);
     } else {
         let q = cols / rows;
         let r = cols % rows;
@@ -250,4 +254,20 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn test_inplace_transpose_workspace() {
+        // Workspaces that fit some or all of the sub-matrices
+        let sizes = [(2, 2), (3, 5), (13, 4), (64, 30), (30, 64)];
+        for (rows, cols) in sizes {
+            for iw in [2, 7, 50, 200, 10000] {
+                let mut src = test_array(rows, cols);
+                let cmp = test_array(rows, cols);
+                let src_sl = src.as_slice_mut().unwrap();
+                let mut w: Vec<f64> = vec![0.; iw];
+                ip_transpose(src_sl, &mut w, rows, cols);
+                assert!(src.into_shape((cols, rows)).unwrap() == cmp.t());
+            }
+        }
+    }
 }
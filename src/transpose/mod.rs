@@ -1,9 +1,7 @@
 //! Fast Transposes of flattened Arrays
-//!
-//! # ToDo
-//!
-//! - Parallel Transposes
 pub mod inplace;
 pub mod outofplace;
+pub mod parallel;
 pub use inplace::ip_transpose;
-pub use outofplace::oop_transpose;
+pub use outofplace::{oop_transpose, oop_transpose_map, oop_transpose_strided_map};
+pub use parallel::{oop_transpose_par, oop_transpose_par_map};
@@ -1,7 +1,7 @@
 //! Out-of-Place Transpose Algorithms
 
 /// Block size of tiling transpose
-const BLOCK_SIZE: usize = 16;
+pub(crate) const BLOCK_SIZE: usize = 16;
 
 /// Size for simple transpose
 const SIZE_SIMPLE: usize = 16 * 16;
@@ -25,12 +25,93 @@ const RECURSION_LIMIT: usize = 128;
 /// * `rows` - Number of rows
 /// * `cols` - Number of cols
 pub fn oop_transpose<T: Copy>(src: &[T], dst: &mut [T], rows: usize, cols: usize) {
+    oop_transpose_map(src, dst, rows, cols, |x| x);
+}
+
+/// Out-of Place transpose, which applies `f` to each element
+///
+/// Same as [`oop_transpose`], but writes `f(x)` instead of `x`.
+/// Fuses elementwise operations, like a scaling, into the transpose.
+///
+/// # Arguments
+///
+/// * `src` - Flattened 2D array with rows * cols elements, input
+/// * `dst` - Flattened 2D array with rows * cols elements, output
+/// * `rows` - Number of rows
+/// * `cols` - Number of cols
+/// * `f` - Elementwise map
+pub fn oop_transpose_map<T: Copy, F: Fn(T) -> T>(
+    src: &[T],
+    dst: &mut [T],
+    rows: usize,
+    cols: usize,
+    f: F,
+) {
     if rows * cols <= SIZE_SIMPLE {
-        oop_transpose_small(src, dst, rows, cols);
+        transpose_small(src, dst, rows, cols, &f);
     } else if rows * cols <= SIZE_TILE {
-        oop_transpose_medium(src, dst, rows, cols, BLOCK_SIZE);
+        transpose_medium(src, dst, rows, cols, BLOCK_SIZE, &f);
     } else {
-        oop_transpose_large(src, dst, rows, cols, BLOCK_SIZE);
+        transpose_large(src, dst, rows, cols, BLOCK_SIZE, &f);
+    }
+}
+
+/// Out-of Place transpose of a block of strided arrays, which applies `f`
+/// to each element and its index
+///
+/// Writes `f(src[r * src_stride + c], r, c)` to `dst[c * dst_stride + r]`
+/// for the rows x cols block. With strides longer than the block, this pads
+/// or truncates the rows of the output, or reads a sub-block of the input.
+/// Uses the loop blocking algorithm of the medium sized [`oop_transpose`].
+///
+/// # Arguments
+///
+/// * `src` - Flattened 2D array with rows of length `src_stride`, input
+/// * `dst` - Flattened 2D array with rows of length `dst_stride`, output
+/// * `rows` - Number of rows of the block in `src`
+/// * `cols` - Number of cols of the block in `src`
+/// * `src_stride` - Distance of the rows of `src`
+/// * `dst_stride` - Distance of the rows of `dst`
+/// * `f` - Elementwise map, which also gets the row and col in `src`
+///
+/// # Panics
+///
+/// Panics if a stride is shorter than the block, or `src` or `dst` are too
+/// short to hold it.
+pub fn oop_transpose_strided_map<T: Copy, F: Fn(T, usize, usize) -> T>(
+    src: &[T],
+    dst: &mut [T],
+    rows: usize,
+    cols: usize,
+    src_stride: usize,
+    dst_stride: usize,
+    f: F,
+) {
+    if rows == 0 || cols == 0 {
+        return;
+    }
+    assert!(src_stride >= cols, "{} < {}", src_stride, cols);
+    assert!(dst_stride >= rows, "{} < {}", dst_stride, rows);
+    let (src_len, dst_len) = (
+        (rows - 1) * src_stride + cols,
+        (cols - 1) * dst_stride + rows,
+    );
+    assert!(src.len() >= src_len, "{} < {}", src.len(), src_len);
+    assert!(dst.len() >= dst_len, "{} < {}", dst.len(), dst_len);
+    for first_row in (0..rows).step_by(BLOCK_SIZE) {
+        let last_row = (first_row + BLOCK_SIZE).min(rows);
+        for first_col in (0..cols).step_by(BLOCK_SIZE) {
+            let last_col = (first_col + BLOCK_SIZE).min(cols);
+            for c in first_col..last_col {
+                for r in first_row..last_row {
+                    let i = c + r * src_stride;
+                    let j = r + c * dst_stride;
+                    unsafe {
+                        *dst.get_unchecked_mut(j) = f(*src.get_unchecked(i), r, c);
+                    }
+                }
+            }
+        }
     }
 }
 
@@ -47,6 +128,16 @@ pub fn oop_transpose<T: Copy>(src: &[T], dst: &mut [T], rows: usize, cols: usize
 ///
 /// src.len() and dst.len() must equal rows * cols
 pub fn oop_transpose_small<T: Copy>(src: &[T], dst: &mut [T], rows: usize, cols: usize) {
+    transpose_small(src, dst, rows, cols, &|x| x);
+}
+
+fn transpose_small<T: Copy, F: Fn(T) -> T>(
+    src: &[T],
+    dst: &mut [T],
+    rows: usize,
+    cols: usize,
+    f: &F,
+) {
     assert!(src.len() == rows * cols, "{} != {}", src.len(), rows * cols);
     assert!(dst.len() == rows * cols, "{} != {}", dst.len(), rows * cols);
 
@@ -55,7 +146,7 @@ pub fn oop_transpose_small<T: Copy>(src: &[T], dst: &mut [T], rows: usize, cols:
             let i = c + r * cols;
             let j = r + c * rows;
             unsafe {
-                *dst.get_unchecked_mut(j) = *src.get_unchecked(i);
+                *dst.get_unchecked_mut(j) = f(*src.get_unchecked(i));
             }
         }
     }
@@ -84,6 +175,17 @@ pub fn oop_transpose_medium<T: Copy>(
     rows: usize,
     cols: usize,
     block_size: usize,
+) {
+    transpose_medium(src, dst, rows, cols, block_size, &|x| x);
+}
+
+fn transpose_medium<T: Copy, F: Fn(T) -> T>(
+    src: &[T],
+    dst: &mut [T],
+    rows: usize,
+    cols: usize,
+    block_size: usize,
+    f: &F,
 ) {
     assert!(src.len() == rows * cols, "{} != {}", src.len(), rows * cols);
     assert!(dst.len() == rows * cols, "{} != {}", dst.len(), rows * cols);
@@ -110,6 +212,7 @@ pub fn oop_transpose_medium<T: Copy>(
                     block_col * block_size,
                     block_size,
                     block_size,
+                    f,
                 );
             }
         }
@@ -130,6 +233,7 @@ pub fn oop_transpose_medium<T: Copy>(
                     cols - remain_cols,
                     block_size,
                     remain_cols,
+                    f,
                 );
             }
         }
@@ -147,6 +251,7 @@ pub fn oop_transpose_medium<T: Copy>(
                     block_col * block_size,
                     remain_rows,
                     block_size,
+                    f,
                 );
             }
         }
@@ -163,6 +268,7 @@ pub fn oop_transpose_medium<T: Copy>(
                 cols - remain_cols,
                 remain_rows,
                 remain_cols,
+                f,
             );
         }
     }
@@ -170,7 +276,7 @@ pub fn oop_transpose_medium<T: Copy>(
 
 /// Transpose a single sub-Tile
 #[allow(clippy::too_many_arguments)]
-unsafe fn transpose_tile<T: Copy>(
+unsafe fn transpose_tile<T: Copy, F: Fn(T) -> T>(
     src: &[T],
     dst: &mut [T],
     rows: usize,
@@ -179,6 +285,7 @@ unsafe fn transpose_tile<T: Copy>(
     first_col: usize,
     num_rows_per_block: usize,
     num_cols_per_block: usize,
+    f: &F,
 ) {
     for tile_col in 0..num_cols_per_block {
         for tile_row in 0..num_rows_per_block {
@@ -186,7 +293,7 @@ unsafe fn transpose_tile<T: Copy>(
             let mat_col = first_col + tile_col;
             let i = mat_col + mat_row * cols;
             let j = mat_row + mat_col * rows;
-            *dst.get_unchecked_mut(j) = *src.get_unchecked(i);
+            *dst.get_unchecked_mut(j) = f(*src.get_unchecked(i));
         }
     }
 }
@@ -214,15 +321,26 @@ pub fn oop_transpose_large<T: Copy>(
     rows: usize,
     cols: usize,
     block_size: usize,
+) {
+    transpose_large(src, dst, rows, cols, block_size, &|x| x);
+}
+
+fn transpose_large<T: Copy, F: Fn(T) -> T>(
+    src: &[T],
+    dst: &mut [T],
+    rows: usize,
+    cols: usize,
+    block_size: usize,
+    f: &F,
 ) {
     assert!(src.len() == rows * cols, "{} != {}", src.len(), rows * cols);
     assert!(dst.len() == rows * cols, "{} != {}", dst.len(), rows * cols);
-    transpose_recursive(src, dst, 0, 0, rows, cols, rows, cols, block_size);
+    transpose_recursive(src, dst, 0, 0, rows, cols, rows, cols, block_size, f);
 }
 
 /// Transpose based on recursive division of rows and cols
 #[allow(clippy::too_many_lines, clippy::too_many_arguments)]
-fn transpose_recursive<T: Copy>(
+fn transpose_recursive<T: Copy, F: Fn(T) -> T>(
     src: &[T],
     dst: &mut [T],
     first_row: usize,
@@ -232,6 +350,7 @@ fn transpose_recursive<T: Copy>(
     total_rows: usize,
     total_cols: usize,
     block_size: usize,
+    f: &F,
 ) {
     if (num_rows <= RECURSION_LIMIT) & (num_cols < RECURSION_LIMIT) {
         // Number of blocks needed
@@ -257,6 +376,7 @@ fn transpose_recursive<T: Copy>(
                         block_col * block_size + first_col,
                         block_size,
                         block_size,
+                        f,
                     );
                 }
             }
@@ -277,6 +397,7 @@ fn transpose_recursive<T: Copy>(
                         num_cols - remain_cols + first_col,
                         block_size,
                         remain_cols,
+                        f,
                     );
                 }
             }
@@ -294,6 +415,7 @@ fn transpose_recursive<T: Copy>(
                         block_col * block_size + first_col,
                         remain_rows,
                         block_size,
+                        f,
                     );
                 }
             }
@@ -310,6 +432,7 @@ fn transpose_recursive<T: Copy>(
                     num_cols - remain_cols + first_col,
                     remain_rows,
                     remain_cols,
+                    f,
                 );
             }
         }
@@ -327,6 +450,7 @@ fn transpose_recursive<T: Copy>(
             total_rows,
             total_cols,
             block_size,
+            f,
         );
         transpose_recursive(
             src,
@@ -338,6 +462,7 @@ fn transpose_recursive<T: Copy>(
             total_rows,
             total_cols,
             block_size,
+            f,
         );
     //
     // Subdivide cols
@@ -353,6 +478,7 @@ fn transpose_recursive<T: Copy>(
             total_rows,
             total_cols,
             block_size,
+            f,
         );
         transpose_recursive(
             src,
@@ -364,6 +490,7 @@ fn transpose_recursive<T: Copy>(
             total_rows,
             total_cols,
             block_size,
+            f,
         );
     }
 }
@@ -412,4 +539,53 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn test_transpose_map() {
+        let sizes = [4, 13, 67, 813];
+
+        for rows in sizes {
+            for cols in sizes {
+                let src = test_array(rows, cols);
+                let src_sl = src.as_slice().unwrap();
+
+                let mut dst = Array2::<f64>::zeros((cols, rows));
+                let dst_sl = dst.as_slice_mut().unwrap();
+                oop_transpose_map(src_sl, dst_sl, rows, cols, |x| 2. * x);
+                assert!(src.t().mapv(|x| 2. * x) == dst);
+            }
+        }
+    }
+
+    #[test]
+    fn test_transpose_strided_map() {
+        let sizes = [4, 13, 67];
+
+        for rows in sizes {
+            for cols in sizes {
+                let src = test_array(rows + 3, cols + 2);
+                let src_sl = src.as_slice().unwrap();
+
+                // Transpose the first rows x cols block into a padded array
+                let mut dst = Array2::<f64>::zeros((cols, rows + 5));
+                let dst_sl = dst.as_slice_mut().unwrap();
+                oop_transpose_strided_map(
+                    src_sl,
+                    dst_sl,
+                    rows,
+                    cols,
+                    cols + 2,
+                    rows + 5,
+                    |x, r, c| x + (r * c) as f64,
+                );
+                for c in 0..cols {
+                    for r in 0..rows {
+                        assert!(dst[[c, r]] == src[[r, c]] + (r * c) as f64);
+                    }
+                    // Padding is untouched
+                    assert!(dst.row(c).iter().skip(rows).all(|x| *x == 0.));
+                }
+            }
+        }
+    }
 }
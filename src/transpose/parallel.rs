@@ -0,0 +1,106 @@
+//! Parallel Out-of-Place Transpose
+//!
+//! The output is split into blocks of rows (columns of the input), which are
+//! transposed independently on the rayon thread pool.
+use super::oop_transpose_map;
+use super::outofplace::BLOCK_SIZE;
+use rayon::prelude::*;
+
+/// Below this size the serial transpose is used
+const SIZE_SERIAL: usize = 64 * 64;
+
+/// Parallel out-of-place transpose
+///
+/// Same as [`super::oop_transpose`], but distributes the work over the
+/// current rayon thread pool.
+///
+/// # Arguments
+///
+/// * `src` - Flattened 2D array with rows * cols elements, input
+/// * `dst` - Flattened 2D array with rows * cols elements, output
+/// * `rows` - Number of rows
+/// * `cols` - Number of cols
+pub fn oop_transpose_par<T: Copy + Send + Sync>(
+    src: &[T],
+    dst: &mut [T],
+    rows: usize,
+    cols: usize,
+) {
+    oop_transpose_par_map(src, dst, rows, cols, |x| x);
+}
+
+/// Parallel out-of-place transpose, which applies `f` to each element
+///
+/// Parallel version of [`super::oop_transpose_map`].
+///
+/// # Arguments
+///
+/// * `src` - Flattened 2D array with rows * cols elements, input
+/// * `dst` - Flattened 2D array with rows * cols elements, output
+/// * `rows` - Number of rows
+/// * `cols` - Number of cols
+/// * `f` - Elementwise map
+pub fn oop_transpose_par_map<T: Copy + Send + Sync, F: Fn(T) -> T + Sync>(
+    src: &[T],
+    dst: &mut [T],
+    rows: usize,
+    cols: usize,
+    f: F,
+) {
+    assert!(src.len() == rows * cols, "{} != {}", src.len(), rows * cols);
+    assert!(dst.len() == rows * cols, "{} != {}", dst.len(), rows * cols);
+    if rows * cols <= SIZE_SERIAL {
+        oop_transpose_map(src, dst, rows, cols, f);
+        return;
+    }
+    // Each task writes BLOCK_SIZE rows of the output
+    dst.par_chunks_mut(rows * BLOCK_SIZE)
+        .enumerate()
+        .for_each(|(i, chunk)| {
+            transpose_cols(src, chunk, rows, cols, i * BLOCK_SIZE, &f);
+        });
+}
+
+/// Transpose the columns `first_col..` of `src` into the rows of `dst`
+///
+/// The number of columns is given by the length of `dst`.
+fn transpose_cols<T: Copy, F: Fn(T) -> T>(
+    src: &[T],
+    dst: &mut [T],
+    rows: usize,
+    cols: usize,
+    first_col: usize,
+    f: &F,
+) {
+    let num_cols = dst.len() / rows;
+    for first_row in (0..rows).step_by(BLOCK_SIZE) {
+        let last_row = (first_row + BLOCK_SIZE).min(rows);
+        for c in 0..num_cols {
+            for r in first_row..last_row {
+                dst[c * rows + r] = f(src[r * cols + first_col + c]);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::transpose::oop_transpose;
+
+    #[test]
+    fn test_transpose_par() {
+        let sizes = [(5, 13), (64, 64), (100, 257), (513, 67)];
+        for (rows, cols) in sizes {
+            let src: Vec<f64> = (0..rows * cols).map(|x| x as f64).collect();
+            let mut expected = vec![0.; rows * cols];
+            oop_transpose(&src, &mut expected, rows, cols);
+            let mut dst = vec![0.; rows * cols];
+            oop_transpose_par(&src, &mut dst, rows, cols);
+            assert!(dst == expected);
+
+            oop_transpose_par_map(&src, &mut dst, rows, cols, |x| 2. * x);
+            assert!(dst.iter().zip(expected.iter()).all(|(a, b)| *a == 2. * b));
+        }
+    }
+}
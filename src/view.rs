@@ -0,0 +1,241 @@
+//! Fft along one axis of (possibly non-contiguous) 2D ndarray views
+//!
+//! The transpose method of [`fft2d_with_transpose_norm`] works on flattened
+//! row-major slices. This module accepts any [`ArrayViewMut2`]:
+//!
+//! - Standard (C) layout views are transformed in place with
+//!   [`fft2d_with_transpose_norm`]
+//! - Fortran layout views are the transpose of a row-major n x m array and
+//!   are transformed in place along the other axis
+//! - All other views, e.g. slices with steps or reversed axes, are gathered
+//!   into the work-space with a blocked strided transpose, such that the lanes
+//!   along `axis` are contiguous. The lanes are transformed with a single call
+//!   to the fft and scattered back, with the normalization applied on the fly
+//!
+//! No memory is allocated, all work-space is provided by the caller, see
+//! [`view_scratch_len`].
+//!
+//! # Example
+//! ```
+//! use ndarray::{s, Array2};
+//! use ndfft_test::ndfft_with_transpose::Normalization;
+//! use ndfft_test::view::{fft2d_view, view_scratch_len};
+//! use rustfft::{num_complex::Complex, FftPlanner};
+//!
+//! let mut a = Array2::<Complex<f64>>::zeros((10, 12));
+//! let mut view = a.slice_mut(s![..;2, 1..9]);
+//! let fft = FftPlanner::new().plan_fft_forward(5);
+//! let mut scratch = vec![Complex::new(0., 0.); view_scratch_len(view.dim(), &fft)];
+//! fft2d_view(view.view_mut(), &mut scratch, &fft, 0, Normalization::Backward);
+//! ```
+use crate::ndfft_with_transpose::{
+    assert_direction, assert_length, fft2d_with_transpose_norm, ifft2d_with_transpose, to_float,
+    Normalization,
+};
+use crate::transpose::outofplace::BLOCK_SIZE;
+use ndarray::{ArrayViewMut2, Axis};
+use rustfft::{num_complex::Complex, Fft, FftDirection, FftNum};
+use std::sync::Arc;
+
+/// Forward fft along `axis` of a 2D view
+///
+/// # Arguments
+///
+/// * `v` - View of an m x n array with arbitrary strides, input and output
+/// * `scratch` - Work-space, at least [`view_scratch_len`] elements
+/// * `fft` - Forward fft plan of length m (`axis` = 0) or n (`axis` = 1)
+/// * `axis` - Axis along which the fft is performed
+/// * `norm` - Normalization of the transform
+///
+/// # Panics
+///
+/// Panics if the length of `fft` does not match the lane length along `axis`,
+/// if `fft` is not a forward plan, or if `scratch` is too small.
+pub fn fft2d_view<T: FftNum>(
+    v: ArrayViewMut2<Complex<T>>,
+    scratch: &mut [Complex<T>],
+    fft: &Arc<dyn Fft<T>>,
+    axis: usize,
+    norm: Normalization,
+) {
+    assert_direction(fft, FftDirection::Forward);
+    fft2d_view_axis(v, scratch, fft, axis, norm);
+}
+
+/// Inverse fft along `axis` of a 2D view
+///
+/// Counterpart of [`fft2d_view`].
+///
+/// # Arguments
+///
+/// * `v` - View of an m x n array with arbitrary strides, input and output
+/// * `scratch` - Work-space, at least [`view_scratch_len`] elements
+/// * `ifft` - Inverse fft plan of length m (`axis` = 0) or n (`axis` = 1)
+/// * `axis` - Axis along which the fft is performed
+/// * `norm` - Normalization of the transform
+///
+/// # Panics
+///
+/// Panics if the length of `ifft` does not match the lane length along `axis`,
+/// if `ifft` is not an inverse plan, or if `scratch` is too small.
+pub fn ifft2d_view<T: FftNum>(
+    v: ArrayViewMut2<Complex<T>>,
+    scratch: &mut [Complex<T>],
+    ifft: &Arc<dyn Fft<T>>,
+    axis: usize,
+    norm: Normalization,
+) {
+    assert_direction(ifft, FftDirection::Inverse);
+    fft2d_view_axis(v, scratch, ifft, axis, norm);
+}
+
+/// Work-space required by [`fft2d_view`] and [`ifft2d_view`] for views of
+/// size `shape`, sufficient for any memory layout
+pub fn view_scratch_len<T: FftNum>(shape: (usize, usize), fft: &Arc<dyn Fft<T>>) -> usize {
+    shape.0 * shape.1 + fft.get_inplace_scratch_len()
+}
+
+/// Fft along a single axis of a view in the direction of `fft`
+fn fft2d_view_axis<T: FftNum>(
+    mut v: ArrayViewMut2<Complex<T>>,
+    scratch: &mut [Complex<T>],
+    fft: &Arc<dyn Fft<T>>,
+    axis: usize,
+    norm: Normalization,
+) {
+    assert!(axis < 2, "axis must be 0 or 1, got {}", axis);
+    let (m, n) = v.dim();
+    assert_length(fft, v.len_of(Axis(axis)), axis);
+    let direction = fft.fft_direction();
+    let process = match direction {
+        FftDirection::Forward => fft2d_with_transpose_norm,
+        FftDirection::Inverse => ifft2d_with_transpose,
+    };
+    if v.is_standard_layout() {
+        process(v.as_slice_mut().unwrap(), scratch, m, n, fft, axis, norm);
+    } else if v.t().is_standard_layout() {
+        // Fortran layout, i.e. a row-major n x m array
+        let data = v.as_slice_memory_order_mut().unwrap();
+        process(data, scratch, n, m, fft, 1 - axis, norm);
+    } else {
+        let scale = norm.factor(fft.len(), direction);
+        // Lanes along `axis` are the rows of `lanes`
+        let mut lanes = if axis == 0 { v.reversed_axes() } else { v };
+        let (buffer, scratch) = scratch.split_at_mut(m * n);
+        gather(&lanes, buffer);
+        fft.process_with_scratch(buffer, scratch);
+        scatter(buffer, &mut lanes, scale);
+    }
+}
+
+/// Copy the rows of the strided view `src` to the contiguous `dst`
+fn gather<T: FftNum>(src: &ArrayViewMut2<Complex<T>>, dst: &mut [Complex<T>]) {
+    let (rows, cols) = src.dim();
+    for first_row in (0..rows).step_by(BLOCK_SIZE) {
+        let last_row = (first_row + BLOCK_SIZE).min(rows);
+        for first_col in (0..cols).step_by(BLOCK_SIZE) {
+            let last_col = (first_col + BLOCK_SIZE).min(cols);
+            for r in first_row..last_row {
+                for c in first_col..last_col {
+                    dst[r * cols + c] = src[[r, c]];
+                }
+            }
+        }
+    }
+}
+
+/// Copy the contiguous `src` to the rows of the strided view `dst`, scaled
+/// by `scale`
+fn scatter<T: FftNum>(src: &[Complex<T>], dst: &mut ArrayViewMut2<Complex<T>>, scale: f64) {
+    let (rows, cols) = dst.dim();
+    let scale = to_float::<T>(scale);
+    for first_row in (0..rows).step_by(BLOCK_SIZE) {
+        let last_row = (first_row + BLOCK_SIZE).min(rows);
+        for first_col in (0..cols).step_by(BLOCK_SIZE) {
+            let last_col = (first_col + BLOCK_SIZE).min(cols);
+            for r in first_row..last_row {
+                for c in first_col..last_col {
+                    dst[[r, c]] = src[r * cols + c] * scale;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ndfft_with_ndrustfft::fft2d_with_ndrustfft;
+    use crate::test_array::{approx_eq, test_vec};
+    use ndarray::{s, Array2};
+    use ndrustfft::FftHandler;
+    use rustfft::FftPlanner;
+
+    /// Compare `fft2d_view` on `view` with ndrustfft on a standard layout copy
+    fn check_view(mut view: ArrayViewMut2<Complex<f64>>, axis: usize) {
+        let expected_in = view.as_standard_layout().into_owned();
+        let (m, n) = view.dim();
+        let len = if axis == 0 { m } else { n };
+        let mut handler: FftHandler<f64> = FftHandler::new(len);
+        let mut expected = Array2::zeros((m, n));
+        fft2d_with_ndrustfft(&expected_in, &mut expected, &mut handler, axis);
+
+        let mut planner = FftPlanner::new();
+        let fft = planner.plan_fft_forward(len);
+        let ifft = planner.plan_fft_inverse(len);
+        let mut scratch = vec![Complex::new(0., 0.); view_scratch_len((m, n), &fft)];
+        fft2d_view(
+            view.view_mut(),
+            &mut scratch,
+            &fft,
+            axis,
+            Normalization::Backward,
+        );
+        let result = view.as_standard_layout().into_owned();
+        assert!(approx_eq(
+            result.as_slice().unwrap(),
+            expected.as_slice().unwrap()
+        ));
+
+        ifft2d_view(
+            view.view_mut(),
+            &mut scratch,
+            &ifft,
+            axis,
+            Normalization::Backward,
+        );
+        let result = view.as_standard_layout().into_owned();
+        assert!(approx_eq(
+            result.as_slice().unwrap(),
+            expected_in.as_slice().unwrap()
+        ));
+    }
+
+    #[test]
+    fn test_fft2d_view() {
+        let (m, n) = (24, 18);
+        for axis in 0..2 {
+            // Standard layout
+            let mut a = Array2::from_shape_vec((m, n), test_vec(m * n)).unwrap();
+            check_view(a.view_mut(), axis);
+
+            // Fortran layout
+            let mut a = Array2::from_shape_vec((n, m), test_vec(m * n)).unwrap();
+            check_view(a.view_mut().reversed_axes(), axis);
+
+            // Slices with steps and negative strides
+            let mut a = Array2::from_shape_vec((m, n), test_vec(m * n)).unwrap();
+            check_view(a.slice_mut(s![1..;2, ..15]), axis);
+            let mut a = Array2::from_shape_vec((m, n), test_vec(m * n)).unwrap();
+            check_view(a.slice_mut(s![..;-1, 2..;3]), axis);
+            let mut a = Array2::from_shape_vec((n, m), test_vec(m * n)).unwrap();
+            check_view(a.slice_mut(s![..;-1, ..]).reversed_axes(), axis);
+
+            // More fft scratch than m * n along axis 0
+            let mut a = Array2::from_shape_vec((509, 3), test_vec(509 * 3)).unwrap();
+            check_view(a.view_mut(), axis);
+            let mut a = Array2::from_shape_vec((3, 509), test_vec(509 * 3)).unwrap();
+            check_view(a.view_mut().reversed_axes(), axis);
+        }
+    }
+}
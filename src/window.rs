@@ -0,0 +1,423 @@
+//! Window functions for tapering data before a transform
+//!
+//! [`Window`] generates the coefficients of the common tapers, which are
+//! applied separably along one or both axes of flat m x n buffers
+//! ([`apply_window`], [`apply_window2d`]) or `Array2` ([`apply_window_array`]).
+//!
+//! [`fft2d_with_window`] and [`fft2d_full_with_window`] fuse the window into
+//! the transform: along axis 0 it is applied during the transpose, along
+//! axis 1 block by block right before the fft of the block, while the block
+//! is still in cache. The scaling of the normalization is folded into the
+//! window coefficients.
+//!
+//! # Example
+//! ```
+//! use ndfft_test::window::{apply_window, Window};
+//!
+//! let (m, n) = (4, 5);
+//! let mut v = vec![1.; m * n];
+//! let hann = Window::Hann.symmetric(n);
+//! assert!((hann[1] - 0.5).abs() < 1e-12 && hann[2] == 1.);
+//! apply_window(&mut v, m, n, &hann, 1);
+//! ```
+use crate::ndfft_with_transpose::{
+    assert_direction, assert_length, to_float, transpose_scaled, Normalization, CHUNK_SIZE,
+};
+use crate::transpose::oop_transpose_strided_map;
+use ndarray::{Array2, Axis};
+use rustfft::{num_complex::Complex, Fft, FftDirection, FftNum};
+use std::f64::consts::PI;
+use std::ops::Mul;
+use std::sync::Arc;
+
+/// Window function
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Window {
+    /// No tapering
+    Rectangular,
+    /// Raised cosine, 0.5 - 0.5 cos(2 pi x)
+    Hann,
+    /// 0.54 - 0.46 cos(2 pi x)
+    Hamming,
+    /// 0.42 - 0.5 cos(2 pi x) + 0.08 cos(4 pi x)
+    Blackman,
+    /// Flat top with cosine tapers over the fraction alpha of the window,
+    /// rectangular for alpha = 0 and Hann for alpha = 1
+    Tukey(f64),
+    /// Kaiser window with shape parameter beta, rectangular for beta = 0
+    Kaiser(f64),
+}
+
+impl Window {
+    /// Symmetric window of length `n`, e.g. for filter design
+    ///
+    /// Same as scipy's `get_window` with `fftbins=False`.
+    pub fn symmetric(self, n: usize) -> Vec<f64> {
+        if n == 1 {
+            return vec![1.];
+        }
+        (0..n)
+            .map(|i| self.evaluate(i as f64 / (n - 1) as f64))
+            .collect()
+    }
+
+    /// Periodic window of length `n`, i.e. the first n points of the
+    /// symmetric window of length n + 1, e.g. for spectral analysis
+    ///
+    /// Same as scipy's `get_window` with `fftbins=True`.
+    pub fn periodic(self, n: usize) -> Vec<f64> {
+        let mut w = self.symmetric(n + 1);
+        w.truncate(n);
+        w
+    }
+
+    /// Value of the window at the normalized position 0 <= x <= 1
+    fn evaluate(self, x: f64) -> f64 {
+        match self {
+            Self::Rectangular => 1.,
+            Self::Hann => 0.5 - 0.5 * (2. * PI * x).cos(),
+            Self::Hamming => 0.54 - 0.46 * (2. * PI * x).cos(),
+            Self::Blackman => 0.42 - 0.5 * (2. * PI * x).cos() + 0.08 * (4. * PI * x).cos(),
+            Self::Tukey(alpha) => {
+                let alpha = alpha.clamp(0., 1.);
+                let edge = x.min(1. - x);
+                if edge < alpha / 2. {
+                    0.5 - 0.5 * (2. * PI * edge / alpha).cos()
+                } else {
+                    1.
+                }
+            }
+            Self::Kaiser(beta) => {
+                let r = 2. * x - 1.;
+                bessel_i0(beta * (1. - r * r).max(0.).sqrt()) / bessel_i0(beta)
+            }
+        }
+    }
+}
+
+/// Modified Bessel function of the first kind of order zero, by its power
+/// series sum ((x / 2)^k / k!)^2
+fn bessel_i0(x: f64) -> f64 {
+    let y = 0.25 * x * x;
+    let mut term = 1.;
+    let mut sum = 1.;
+    let mut k = 1.;
+    while term > f64::EPSILON * sum {
+        term *= y / (k * k);
+        sum += term;
+        k += 1.;
+    }
+    sum
+}
+
+/// Multiply each lane along `axis` of a flattened m x n array by `window`
+///
+/// # Panics
+///
+/// Panics if `v` does not have m * n elements or `window` does not match
+/// the length of the lanes.
+pub fn apply_window<T: Copy + Mul<f64, Output = T>>(
+    v: &mut [T],
+    m: usize,
+    n: usize,
+    window: &[f64],
+    axis: usize,
+) {
+    assert!(v.len() == m * n, "{} != {}", v.len(), m * n);
+    match axis {
+        0 => {
+            assert!(window.len() == m, "{} != {}", window.len(), m);
+            for (row, w) in v.chunks_exact_mut(n.max(1)).zip(window) {
+                row.iter_mut().for_each(|x| *x = *x * *w);
+            }
+        }
+        1 => {
+            assert!(window.len() == n, "{} != {}", window.len(), n);
+            for row in v.chunks_exact_mut(n.max(1)) {
+                row.iter_mut().zip(window).for_each(|(x, w)| *x = *x * *w);
+            }
+        }
+        _ => panic!("axis must be 0 or 1, got {}", axis),
+    }
+}
+
+/// Multiply a flattened m x n array by the separable window `w0[i] * w1[j]`
+///
+/// # Panics
+///
+/// Panics if `v` does not have m * n elements, `w0` does not have m or `w1`
+/// does not have n elements.
+pub fn apply_window2d<T: Copy + Mul<f64, Output = T>>(
+    v: &mut [T],
+    m: usize,
+    n: usize,
+    w0: &[f64],
+    w1: &[f64],
+) {
+    assert!(v.len() == m * n, "{} != {}", v.len(), m * n);
+    assert!(w0.len() == m, "{} != {}", w0.len(), m);
+    assert!(w1.len() == n, "{} != {}", w1.len(), n);
+    for (row, a) in v.chunks_exact_mut(n.max(1)).zip(w0) {
+        for (x, b) in row.iter_mut().zip(w1) {
+            *x = *x * (a * b);
+        }
+    }
+}
+
+/// Multiply each lane along `axis` of an `Array2` by `window`
+///
+/// Works for any memory layout of `a`.
+///
+/// # Panics
+///
+/// Panics if `window` does not match the length of the lanes.
+pub fn apply_window_array<T: Copy + Mul<f64, Output = T>>(
+    a: &mut Array2<T>,
+    window: &[f64],
+    axis: usize,
+) {
+    let len = a.len_of(Axis(axis));
+    assert!(window.len() == len, "{} != {}", window.len(), len);
+    // The i-th subview along `axis` holds the i-th element of every lane
+    for (mut sub, w) in a.axis_iter_mut(Axis(axis)).zip(window) {
+        sub.mapv_inplace(|x| x * *w);
+    }
+}
+
+/// Forward fft along `axis` of a flattened m x n array, tapered by `window`
+///
+/// Same as [`apply_window`] followed by
+/// [`crate::ndfft_with_transpose::fft2d_with_transpose_norm`],
+/// without an extra sweep over the data.
+///
+/// # Arguments
+///
+/// * `v` - Flattened 2D array with m * n elements, input and output
+/// * `scratch` - Work-space, at least m * n + `fft.get_inplace_scratch_len()`
+///   elements for `axis` = 0 and `fft.get_inplace_scratch_len()` elements
+///   for `axis` = 1
+/// * `m` - Number of rows
+/// * `n` - Number of cols
+/// * `fft` - Forward fft plan of length m (`axis` = 0) or n (`axis` = 1)
+/// * `axis` - Axis along which the fft is performed
+/// * `norm` - Normalization of the transform
+/// * `window` - Window of the length of the lanes along `axis`
+///
+/// # Panics
+///
+/// Panics if the length of `fft` or `window` does not match the lane length
+/// along `axis`, if `fft` is not a forward plan, or if `v` or `scratch` are
+/// too small.
+#[allow(clippy::too_many_arguments)]
+pub fn fft2d_with_window<T: FftNum>(
+    v: &mut [Complex<T>],
+    scratch: &mut [Complex<T>],
+    m: usize,
+    n: usize,
+    fft: &Arc<dyn Fft<T>>,
+    axis: usize,
+    norm: Normalization,
+    window: &[f64],
+) {
+    assert_direction(fft, FftDirection::Forward);
+    assert!(v.len() == m * n, "{} != {}", v.len(), m * n);
+    let len = if axis == 0 { m } else { n };
+    assert!(window.len() == len, "{} != {}", window.len(), len);
+    assert_length(fft, len, axis);
+    let scale = norm.factor(len, FftDirection::Forward);
+    let window: Vec<T> = window.iter().map(|w| to_float(w * scale)).collect();
+    match axis {
+        0 => windowed_axis0(v, scratch, m, n, fft, &window),
+        1 => windowed_axis1(v, scratch, n, fft, &window),
+        _ => panic!("axis must be 0 or 1, got {}", axis),
+    }
+}
+
+/// Forward fft along both axes of a flattened m x n array, tapered by the
+/// separable window `w0[i] * w1[j]`
+///
+/// Same as [`apply_window2d`] followed by [`crate::ndfft_with_transpose::fft2d_full`],
+/// without an extra sweep over the data.
+///
+/// # Arguments
+///
+/// * `v` - Flattened 2D array with m * n elements, input and output
+/// * `scratch` - Work-space of at least m * n + `fft_m.get_inplace_scratch_len()`
+///   and `fft_n.get_inplace_scratch_len()` elements
+/// * `m` - Number of rows
+/// * `n` - Number of cols
+/// * `fft_m` - Forward fft plan of length m
+/// * `fft_n` - Forward fft plan of length n
+/// * `norm` - Normalization of the transform
+/// * `w0` - Window of length m along axis 0
+/// * `w1` - Window of length n along axis 1
+///
+/// # Panics
+///
+/// Panics if the plans or windows do not match the shape, if the plans are
+/// not forward plans, or if `v` or `scratch` are too small.
+#[allow(clippy::too_many_arguments)]
+pub fn fft2d_full_with_window<T: FftNum>(
+    v: &mut [Complex<T>],
+    scratch: &mut [Complex<T>],
+    m: usize,
+    n: usize,
+    fft_m: &Arc<dyn Fft<T>>,
+    fft_n: &Arc<dyn Fft<T>>,
+    norm: Normalization,
+    w0: &[f64],
+    w1: &[f64],
+) {
+    assert_direction(fft_m, FftDirection::Forward);
+    assert_direction(fft_n, FftDirection::Forward);
+    assert!(v.len() == m * n, "{} != {}", v.len(), m * n);
+    assert!(w0.len() == m, "{} != {}", w0.len(), m);
+    assert!(w1.len() == n, "{} != {}", w1.len(), n);
+    assert_length(fft_m, m, 0);
+    assert_length(fft_n, n, 1);
+    let scale = norm.factor(m * n, FftDirection::Forward);
+    let w0: Vec<T> = w0.iter().map(|w| to_float(w * scale)).collect();
+    let w1: Vec<T> = w1.iter().map(|w| to_float(*w)).collect();
+    windowed_axis1(v, scratch, n, fft_n, &w1);
+    windowed_axis0(v, scratch, m, n, fft_m, &w0);
+}
+
+/// Windowed transpose, fft of the now contiguous lanes and transpose back
+fn windowed_axis0<T: FftNum>(
+    v: &mut [Complex<T>],
+    scratch: &mut [Complex<T>],
+    m: usize,
+    n: usize,
+    fft: &Arc<dyn Fft<T>>,
+    window: &[T],
+) {
+    let extra_len = fft.get_inplace_scratch_len();
+    assert!(scratch.len() >= m * n + extra_len);
+    let (buffer, extra) = scratch.split_at_mut(m * n);
+    oop_transpose_strided_map(v, buffer, m, n, n, m, |x, r, _| x * window[r]);
+    fft.process_with_scratch(buffer, &mut extra[..extra_len]);
+    transpose_scaled(buffer, v, n, m, 1.);
+}
+
+/// Window and transform blocks of contiguous lanes of length `n`
+fn windowed_axis1<T: FftNum>(
+    v: &mut [Complex<T>],
+    scratch: &mut [Complex<T>],
+    n: usize,
+    fft: &Arc<dyn Fft<T>>,
+    window: &[T],
+) {
+    let scratch_len = fft.get_inplace_scratch_len();
+    assert!(scratch.len() >= scratch_len);
+    let lanes_per_chunk = (CHUNK_SIZE / n.max(1)).max(1);
+    for chunk in v.chunks_mut(lanes_per_chunk * n.max(1)) {
+        for lane in chunk.chunks_exact_mut(n.max(1)) {
+            lane.iter_mut().zip(window).for_each(|(x, w)| *x = *x * *w);
+        }
+        fft.process_with_scratch(chunk, &mut scratch[..scratch_len]);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ndfft_with_transpose::{fft2d_full, fft2d_with_transpose_norm};
+    use crate::test_array::{approx_eq, test_vec};
+    use rustfft::FftPlanner;
+
+    fn assert_close(result: &[f64], expected: &[f64]) {
+        assert!(result.len() == expected.len());
+        for (a, b) in result.iter().zip(expected.iter()) {
+            assert!((a - b).abs() < 1e-8, "{} != {}", a, b);
+        }
+    }
+
+    #[test]
+    fn test_windows() {
+        // scipy.signal.get_window(..., 5, fftbins=False), Kaiser from the I0 series
+        assert_close(&Window::Hann.symmetric(5), &[0., 0.5, 1., 0.5, 0.]);
+        assert_close(&Window::Hamming.symmetric(5), &[0.08, 0.54, 1., 0.54, 0.08]);
+        assert_close(&Window::Blackman.symmetric(5), &[0., 0.34, 1., 0.34, 0.]);
+        assert_close(
+            &Window::Kaiser(5.).symmetric(5),
+            &[0.0367108921, 0.5528517697, 1., 0.5528517697, 0.0367108921],
+        );
+        assert_close(&Window::Kaiser(0.).symmetric(4), &[1.; 4]);
+        assert_close(&Window::Tukey(0.).symmetric(6), &[1.; 6]);
+        assert_close(&Window::Tukey(1.).symmetric(7), &Window::Hann.symmetric(7));
+        assert_close(
+            &Window::Tukey(0.5).symmetric(9),
+            &[0., 0.5, 1., 1., 1., 1., 1., 0.5, 0.],
+        );
+        // fftbins=True
+        assert_close(&Window::Hann.periodic(4), &[0., 0.5, 1., 0.5]);
+    }
+
+    #[test]
+    fn test_apply_window_array() {
+        let (m, n) = (4, 6);
+        let w0 = Window::Hann.periodic(m);
+        let w1 = Window::Hamming.periodic(n);
+        let v: Vec<f64> = (0..m * n).map(|x| x as f64).collect();
+        let mut expected = v.clone();
+        apply_window2d(&mut expected, m, n, &w0, &w1);
+
+        let mut flat = v.clone();
+        apply_window(&mut flat, m, n, &w0, 0);
+        apply_window(&mut flat, m, n, &w1, 1);
+        assert_close(&flat, &expected);
+
+        // Fortran order
+        let mut a = Array2::from_shape_vec((m, n), v).unwrap();
+        a = a
+            .reversed_axes()
+            .as_standard_layout()
+            .into_owned()
+            .reversed_axes();
+        apply_window_array(&mut a, &w0, 0);
+        apply_window_array(&mut a, &w1, 1);
+        assert_close(a.as_standard_layout().as_slice().unwrap(), &expected);
+    }
+
+    #[test]
+    fn test_fft2d_with_window() {
+        // (509, 3) needs more fft scratch than m * n along axis 0
+        for (m, n) in [(24, 18), (509, 3)] {
+            let mut planner = FftPlanner::new();
+            let fft_m = planner.plan_fft_forward(m);
+            let fft_n = planner.plan_fft_forward(n);
+            let extra = fft_m
+                .get_inplace_scratch_len()
+                .max(fft_n.get_inplace_scratch_len());
+            let mut scratch = vec![Complex::new(0., 0.); m * n + extra];
+            let w0 = Window::Kaiser(4.).periodic(m);
+            let w1 = Window::Tukey(0.3).periodic(n);
+            for norm in [Normalization::Backward, Normalization::Ortho] {
+                for (axis, fft, w) in [(0, &fft_m, &w0), (1, &fft_n, &w1)] {
+                    let mut expected = test_vec(m * n);
+                    apply_window(&mut expected, m, n, w, axis);
+                    fft2d_with_transpose_norm(&mut expected, &mut scratch, m, n, fft, axis, norm);
+                    let mut result = test_vec(m * n);
+                    fft2d_with_window(&mut result, &mut scratch, m, n, fft, axis, norm, w);
+                    assert!(approx_eq(&result, &expected));
+                }
+                let mut expected = test_vec(m * n);
+                apply_window2d(&mut expected, m, n, &w0, &w1);
+                fft2d_full(&mut expected, &mut scratch, m, n, &fft_m, &fft_n, norm);
+                let mut result = test_vec(m * n);
+                fft2d_full_with_window(
+                    &mut result,
+                    &mut scratch,
+                    m,
+                    n,
+                    &fft_m,
+                    &fft_n,
+                    norm,
+                    &w0,
+                    &w1,
+                );
+                assert!(approx_eq(&result, &expected));
+            }
+        }
+    }
+}
@@ -0,0 +1,389 @@
+//! Persistent store of calibrated strategy choices ("wisdom")
+//!
+//! Calibrating the backend of [`Fft2dStrategyPlan`] takes time on every
+//! start-up. [`Wisdom`] records the outcome per [`WisdomKey`], i.e. per shape,
+//! axis, precision and thread count, and is saved to a plain text file:
+//!
+//! ```text
+//! # ndfft_test wisdom v1
+//! cpu Intel(R) Xeon(R) Processor
+//! 128 64 0 f64 8 transpose 51200 98304
+//! ```
+//!
+//! Each entry line holds m, n, axis, precision, threads, strategy and the
+//! timings of the transpose and the lanes backend in nanoseconds. A file recorded on a different CPU model is
+//! discarded on load.
+//!
+//! # Example
+//! ```
+//! use ndfft_test::strategy::{Fft2dStrategyPlan, Strategy};
+//! use ndfft_test::wisdom::Wisdom;
+//! use rustfft::FftDirection;
+//!
+//! let name = format!("ndfft_test_wisdom_example_{}.txt", std::process::id());
+//! let path = std::env::temp_dir().join(name);
+//! let mut wisdom = Wisdom::load(&path).unwrap();
+//! let plan = Fft2dStrategyPlan::new((64, 32), 0, FftDirection::Forward, Strategy::Auto)
+//!     .calibrate_with_wisdom(3, &mut wisdom);
+//! wisdom.save(&path).unwrap();
+//! # std::fs::remove_file(&path).unwrap();
+//! ```
+//!
+//! [`Fft2dStrategyPlan`]: crate::strategy::Fft2dStrategyPlan
+use crate::strategy::{Calibration, Strategy};
+use rustfft::FftNum;
+use std::collections::BTreeMap;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use std::time::Duration;
+
+/// First line of a wisdom file
+const HEADER: &str = "# ndfft_test wisdom v1";
+
+/// Float precision of a transform
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Precision {
+    F32,
+    F64,
+}
+
+impl Precision {
+    /// Precision of the float type `T`
+    pub fn of<T: FftNum>() -> Self {
+        if std::mem::size_of::<T>() == std::mem::size_of::<f32>() {
+            Self::F32
+        } else {
+            Self::F64
+        }
+    }
+}
+
+/// Problem a wisdom entry applies to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct WisdomKey {
+    /// Shape (m, n) of the array
+    pub shape: (usize, usize),
+    /// Axis along which the fft is performed
+    pub axis: usize,
+    /// Float precision of the data
+    pub precision: Precision,
+    /// Number of threads of the rayon pool
+    pub threads: usize,
+}
+
+/// Recorded outcome of a calibration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WisdomEntry {
+    /// Selected backend, never [`Strategy::Auto`]
+    pub strategy: Strategy,
+    /// Measured timings of both backends
+    pub calibration: Calibration,
+}
+
+/// Collection of wisdom entries, valid for a single CPU model
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Wisdom {
+    cpu: String,
+    entries: BTreeMap<WisdomKey, WisdomEntry>,
+}
+
+impl Default for Wisdom {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Wisdom {
+    /// Empty wisdom for the CPU model of the host
+    pub fn new() -> Self {
+        Self {
+            cpu: cpu_model(),
+            entries: BTreeMap::new(),
+        }
+    }
+
+    /// Load wisdom from `path`
+    ///
+    /// Returns empty wisdom if the file does not exist or was recorded on a
+    /// different CPU model.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or is malformed.
+    pub fn load<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => {
+                let wisdom = Self::parse(&text)?;
+                if wisdom.cpu == cpu_model() {
+                    Ok(wisdom)
+                } else {
+                    Ok(Self::new())
+                }
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Self::new()),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Save wisdom to `path`, overwriting an existing file
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        std::fs::write(path, self.to_string())
+    }
+
+    /// CPU model the wisdom was recorded on
+    pub fn cpu(&self) -> &str {
+        &self.cpu
+    }
+
+    /// Entry of `key`, if any
+    pub fn get(&self, key: &WisdomKey) -> Option<&WisdomEntry> {
+        self.entries.get(key)
+    }
+
+    /// Record `entry` for `key`, replacing a previous entry
+    ///
+    /// # Panics
+    ///
+    /// Panics if the strategy of `entry` is [`Strategy::Auto`].
+    pub fn insert(&mut self, key: WisdomKey, entry: WisdomEntry) {
+        assert!(
+            entry.strategy != Strategy::Auto,
+            "wisdom entries must record a selected strategy, got Auto"
+        );
+        self.entries.insert(key, entry);
+    }
+
+    /// Number of entries
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// True if there are no entries
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Parse the text of a wisdom file
+    fn parse(text: &str) -> std::io::Result<Self> {
+        let mut lines = text.lines();
+        if lines.next() != Some(HEADER) {
+            return Err(invalid("missing wisdom header"));
+        }
+        let cpu = lines
+            .next()
+            .and_then(|line| line.strip_prefix("cpu "))
+            .ok_or_else(|| invalid("missing cpu model"))?;
+        let mut wisdom = Self {
+            cpu: cpu.to_string(),
+            entries: BTreeMap::new(),
+        };
+        for line in lines.filter(|line| !line.trim().is_empty()) {
+            let (key, entry) = parse_entry(line).ok_or_else(|| invalid(line))?;
+            wisdom.insert(key, entry);
+        }
+        Ok(wisdom)
+    }
+}
+
+impl std::fmt::Display for Wisdom {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}\ncpu {}", HEADER, self.cpu)?;
+        for (key, entry) in &self.entries {
+            let precision = match key.precision {
+                Precision::F32 => "f32",
+                Precision::F64 => "f64",
+            };
+            let strategy = match entry.strategy {
+                Strategy::Transpose => "transpose",
+                Strategy::Lanes => "lanes",
+                Strategy::Auto => unreachable!("wisdom entries are never Auto"),
+            };
+            writeln!(
+                f,
+                "{} {} {} {} {} {} {} {}",
+                key.shape.0,
+                key.shape.1,
+                key.axis,
+                precision,
+                key.threads,
+                strategy,
+                entry.calibration.transpose.as_nanos(),
+                entry.calibration.lanes.as_nanos(),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse an entry line, `None` if it is malformed
+fn parse_entry(line: &str) -> Option<(WisdomKey, WisdomEntry)> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    if fields.len() != 8 {
+        return None;
+    }
+    let number = |i: usize| fields[i].parse::<usize>().ok();
+    let nanos = |i: usize| fields[i].parse::<u64>().ok().map(Duration::from_nanos);
+    let precision = match fields[3] {
+        "f32" => Precision::F32,
+        "f64" => Precision::F64,
+        _ => return None,
+    };
+    let strategy = match fields[5] {
+        "transpose" => Strategy::Transpose,
+        "lanes" => Strategy::Lanes,
+        _ => return None,
+    };
+    let key = WisdomKey {
+        shape: (number(0)?, number(1)?),
+        axis: number(2)?,
+        precision,
+        threads: number(4)?,
+    };
+    let entry = WisdomEntry {
+        strategy,
+        calibration: Calibration {
+            transpose: nanos(6)?,
+            lanes: nanos(7)?,
+        },
+    };
+    Some((key, entry))
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("invalid wisdom file: {}", msg),
+    )
+}
+
+/// CPU model of the host, from /proc/cpuinfo where available
+///
+/// Falls back to the target architecture.
+pub fn cpu_model() -> String {
+    std::fs::read_to_string("/proc/cpuinfo")
+        .ok()
+        .and_then(|info| {
+            info.lines()
+                .find(|line| line.starts_with("model name"))
+                .and_then(|line| line.split_once(':'))
+                .map(|(_, model)| model.trim().to_string())
+        })
+        .unwrap_or_else(|| std::env::consts::ARCH.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::strategy::Fft2dStrategyPlan;
+    use rustfft::FftDirection;
+    use std::path::PathBuf;
+
+    /// Temporary file of the test `name`, unique per process
+    fn temp_path(name: &str) -> PathBuf {
+        let file = format!("ndfft_test_{}_{}.txt", name, std::process::id());
+        std::env::temp_dir().join(file)
+    }
+
+    fn example_entry() -> (WisdomKey, WisdomEntry) {
+        let key = WisdomKey {
+            shape: (128, 64),
+            axis: 0,
+            precision: Precision::F64,
+            threads: 4,
+        };
+        let entry = WisdomEntry {
+            strategy: Strategy::Lanes,
+            calibration: Calibration {
+                transpose: Duration::from_nanos(2000),
+                lanes: Duration::from_nanos(1000),
+            },
+        };
+        (key, entry)
+    }
+
+    #[test]
+    fn test_wisdom_file() {
+        let path = temp_path("wisdom_file");
+        let (key, entry) = example_entry();
+        let mut wisdom = Wisdom::new();
+        wisdom.insert(key, entry);
+        wisdom.save(&path).unwrap();
+        let loaded = Wisdom::load(&path).unwrap();
+        assert!(loaded == wisdom);
+        assert!(loaded.get(&key) == Some(&entry));
+
+        // Recorded on a different cpu
+        let text = wisdom.to_string().replace(wisdom.cpu(), "Another CPU");
+        std::fs::write(&path, text).unwrap();
+        assert!(Wisdom::load(&path).unwrap().is_empty());
+
+        std::fs::write(&path, "not a wisdom file").unwrap();
+        assert!(Wisdom::load(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+        assert!(Wisdom::load(&path).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_wisdom_round_trip() {
+        let path = temp_path("wisdom_round_trip");
+        let (key, entry) = example_entry();
+        let mut wisdom = Wisdom::new();
+        for (axis, strategy) in [Strategy::Transpose, Strategy::Lanes]
+            .into_iter()
+            .enumerate()
+        {
+            for precision in [Precision::F32, Precision::F64] {
+                let key = WisdomKey {
+                    axis,
+                    precision,
+                    ..key
+                };
+                wisdom.insert(key, WisdomEntry { strategy, ..entry });
+            }
+        }
+        wisdom.save(&path).unwrap();
+        let loaded = Wisdom::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(loaded.len() == 4);
+        assert!(loaded == wisdom);
+    }
+
+    #[test]
+    fn test_precision() {
+        assert!(Precision::of::<f32>() == Precision::F32);
+        assert!(Precision::of::<f64>() == Precision::F64);
+    }
+
+    #[test]
+    #[should_panic(expected = "got Auto")]
+    fn test_wisdom_insert_auto() {
+        let (key, entry) = example_entry();
+        let entry = WisdomEntry {
+            strategy: Strategy::Auto,
+            ..entry
+        };
+        Wisdom::new().insert(key, entry);
+    }
+
+    #[test]
+    fn test_plan_with_wisdom() {
+        let (_, entry) = example_entry();
+        let plan = Fft2dStrategyPlan::new((128, 64), 0, FftDirection::Forward, Strategy::Auto);
+        let mut wisdom = Wisdom::new();
+        wisdom.insert(plan.wisdom_key(), entry);
+        let plan = plan.calibrate_with_wisdom(1, &mut wisdom);
+        assert!(plan.strategy() == Strategy::Lanes);
+        assert!(plan.calibration() == Some(entry.calibration));
+
+        // Unknown problems are calibrated and recorded
+        let plan = Fft2dStrategyPlan::new((32, 16), 1, FftDirection::Forward, Strategy::Auto)
+            .calibrate_with_wisdom(1, &mut wisdom);
+        assert!(wisdom.len() == 2);
+        let recorded = wisdom.get(&plan.wisdom_key()).unwrap();
+        assert!(recorded.strategy == plan.strategy());
+    }
+}